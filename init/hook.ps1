@@ -0,0 +1,54 @@
+# PowerShell cannot source the store, so the variables are rendered by gexport and applied from the
+# prompt whenever the store has changed.
+
+$env:GEXPORT_SOURCE_FILE = if ($env:XDG_CONFIG_HOME) {
+    Join-Path $env:XDG_CONFIG_HOME 'gexport/gexports'
+} else {
+    Join-Path $HOME '.config/gexport/gexports'
+}
+
+# env-var file set up by the administrator for all users, see --system
+$global:GEXPORT_SYSTEM_FILE = & gexport --system-file
+
+# empty for a missing file, so the system store is picked up once it is set up
+function global:__gexport_mod_time($file) {
+    if (Test-Path $file) {
+        [string][DateTimeOffset]::new((Get-Item $file).LastWriteTimeUtc).ToUnixTimeSeconds()
+    } else {
+        ''
+    }
+}
+
+function global:__gexport_source {
+    if (-not (Test-Path $env:GEXPORT_SOURCE_FILE)) {
+        New-Item -ItemType File -Force $env:GEXPORT_SOURCE_FILE | Out-Null
+    }
+    # exported for 'gexport diff' and 'gexport doctor' to tell whether this shell is up to date
+    $env:GEXPORT_MOD_TIME = __gexport_mod_time $env:GEXPORT_SOURCE_FILE
+    $global:GEXPORT_SYSTEM_MOD_TIME = __gexport_mod_time $global:GEXPORT_SYSTEM_FILE
+    Remove-Variable -Name GEXPORT_REFRESH_AT -Scope Global -ErrorAction SilentlyContinue
+    (& gexport --render pwsh) -join "`n" | Invoke-Expression
+}
+
+function global:__gexport_prompt {
+    $now = [DateTimeOffset]::UtcNow.ToUnixTimeSeconds()
+    if ($global:GEXPORT_REFRESH_AT -and $now -ge [long]$global:GEXPORT_REFRESH_AT) {
+        & gexport --recompute
+    }
+    if (-not (Test-Path $env:GEXPORT_SOURCE_FILE) -or
+        (__gexport_mod_time $env:GEXPORT_SOURCE_FILE) -ne $env:GEXPORT_MOD_TIME -or
+        (__gexport_mod_time $global:GEXPORT_SYSTEM_FILE) -ne $global:GEXPORT_SYSTEM_MOD_TIME) {
+        __gexport_source
+    }
+}
+
+__gexport_source
+
+if (-not $global:__gexport_prompt_registered) {
+    $global:__gexport_prompt_registered = $true
+    $global:__gexport_original_prompt = $function:prompt
+    function global:prompt {
+        __gexport_prompt
+        & $global:__gexport_original_prompt
+    }
+}
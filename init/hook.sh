@@ -0,0 +1,51 @@
+# POSIX sh neither has preexec nor can it source the store, so the variables are rendered by
+# gexport and reloaded by a trap which the prompt triggers whenever the store has changed.
+
+if [ -n "$XDG_CONFIG_HOME" ]; then
+    GEXPORT_SOURCE_FILE="${XDG_CONFIG_HOME}/gexport/gexports"
+elif [ -n "$HOME" ]; then
+    GEXPORT_SOURCE_FILE="${HOME}/.config/gexport/gexports"
+else
+    return 1
+fi
+# exported for 'gexport doctor' to check the hook against its own config
+export GEXPORT_SOURCE_FILE
+# env-var file set up by the administrator for all users, see --system
+GEXPORT_SYSTEM_FILE="$(command gexport --system-file)"
+
+__gexport_ensure_files() {
+    if [ ! -f "$GEXPORT_SOURCE_FILE" ]; then
+        mkdir -p "$(dirname "$GEXPORT_SOURCE_FILE")"
+        touch "$GEXPORT_SOURCE_FILE"
+    fi
+}
+
+__gexport_source() {
+    __gexport_ensure_files
+    GEXPORT_MOD_TIME="$(stat -c %Y "$GEXPORT_SOURCE_FILE")"
+    # empty while there is no system store, so the shell picks it up once it is set up
+    GEXPORT_SYSTEM_MOD_TIME="$(stat -c %Y "$GEXPORT_SYSTEM_FILE" 2>/dev/null)"
+    unset GEXPORT_REFRESH_AT
+    eval "$(command gexport --render sh)"
+}
+
+# runs in a subshell of the prompt, which cannot change the variables of the shell itself
+__gexport_prompt() {
+    if [ -n "$GEXPORT_REFRESH_AT" ] && [ "$(date +%s)" -ge "$GEXPORT_REFRESH_AT" ]; then
+        command gexport --recompute
+    fi
+    if [ "$(stat -c %Y "$GEXPORT_SOURCE_FILE" 2>/dev/null)" != "$GEXPORT_MOD_TIME" ] \
+        || [ "$(stat -c %Y "$GEXPORT_SYSTEM_FILE" 2>/dev/null)" != "$GEXPORT_SYSTEM_MOD_TIME" ]; then
+        kill -USR1 $$
+    fi
+}
+
+__gexport_source
+# exported for 'gexport diff' and 'gexport doctor' to tell whether this shell is up to date
+export GEXPORT_MOD_TIME
+
+trap '__gexport_source' USR1
+if [ -z "$GEXPORT_PROMPT_REGISTERED" ]; then
+    PS1='$(__gexport_prompt)'"$PS1"
+    GEXPORT_PROMPT_REGISTERED=1
+fi
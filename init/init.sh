@@ -10,6 +10,8 @@ __gexport_config_dir() {
 }
 
 GEXPORT_SOURCE_FILE="$(__gexport_config_dir)" || return 1
+# exported for 'gexport doctor' to check the hook against its own config
+export GEXPORT_SOURCE_FILE
 
 __gexport_ensure_files() {
     if [ ! -f "$GEXPORT_SOURCE_FILE" ]; then
@@ -19,15 +21,40 @@ __gexport_ensure_files() {
 }
 
 __gexport_ensure_files
-GEXPORT_MOD_TIME="$(stat -c %Y "$GEXPORT_SOURCE_FILE")"
+# exported for 'gexport diff' and 'gexport doctor' to tell whether this shell is up to date
+export GEXPORT_MOD_TIME="$(stat -c %Y "$GEXPORT_SOURCE_FILE")"
+
+# env-var file set up by the administrator for all users, see --system
+GEXPORT_SYSTEM_FILE="$(command gexport --system-file)"
+# empty while there is no system store, so the shells pick it up once it is set up
+GEXPORT_SYSTEM_MOD_TIME="$(stat -c %Y "$GEXPORT_SYSTEM_FILE" 2>/dev/null)"
+# env-var files of the tmux / screen session and terminal this shell is in, see --scope
+GEXPORT_SCOPE_FILES="$(command gexport --scope-files)"
+
+__gexport_source() {
+    unset GEXPORT_REFRESH_AT
+    # records the values the variables had before gexport set them and restores deleted ones
+    eval "$(command gexport --tombstones)"
+    [ -f "$GEXPORT_SYSTEM_FILE" ] && source "$GEXPORT_SYSTEM_FILE"
+    source "$GEXPORT_SOURCE_FILE"
+    local scope_file
+    while IFS= read -r scope_file; do
+        [ -f "$scope_file" ] && source "$scope_file"
+    done <<< "$GEXPORT_SCOPE_FILES"
+}
 
 __gexport_preexec() {
     __gexport_ensure_files
+    if [ -n "$GEXPORT_REFRESH_AT" ] && [ "${EPOCHSECONDS:-$(date +%s)}" -ge "$GEXPORT_REFRESH_AT" ]; then
+        command gexport --recompute
+    fi
     local mod_time="$(stat -c %Y "$GEXPORT_SOURCE_FILE")"
-    if [ "$mod_time" -ne "$GEXPORT_MOD_TIME" ]; then
-        source "$GEXPORT_SOURCE_FILE"
+    local system_mod_time="$(stat -c %Y "$GEXPORT_SYSTEM_FILE" 2>/dev/null)"
+    if [ "$mod_time" -ne "$GEXPORT_MOD_TIME" ] || [ "$system_mod_time" != "$GEXPORT_SYSTEM_MOD_TIME" ]; then
+        __gexport_source
         GEXPORT_MOD_TIME="$mod_time"
+        GEXPORT_SYSTEM_MOD_TIME="$system_mod_time"
     fi
 }
 
-source "$GEXPORT_SOURCE_FILE"
+__gexport_source
@@ -1,62 +1,279 @@
+use crate::str;
 use crate::utils::*;
+use crate::env_vars::Condition;
+use crate::completions;
 use std::ffi::OsString;
 use std::path::PathBuf;
-use clap::{Args, Parser, ValueEnum};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use clap::builder::styling::{Styles, AnsiColor, Effects, Style};
+use clap_complete::ArgValueCompleter;
 use clap_stdin::FileOrStdin;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 #[command(styles = STYLE)]
 #[command(override_usage = "\
-\tgexport [-p [NAME]...]
-\tgexport [-udn] <NAME[=VALUE]>...
-\tgexport --import [FILE]
+\tgexport [-p [NAME|PATTERN]...] [--regex] [--long] [--tag <TAG>]... [--scope <SCOPE>|--system|--layers]
+\tgexport [-udntcr] [--integer|--lower|--upper] [--force] [--when <KEY=PATTERN>]... [--describe <TEXT>] [--tag <TAG>]... [--scope <SCOPE>|--system] [--dry-run] <NAME[=VALUE]>...
+\tgexport -d|-u [--regex] [--force] [--scope <SCOPE>|--system] [--dry-run] <PATTERN>...
+\tgexport --command|--builtin [--refresh <DURATION>] <NAME=VALUE>...
+\tgexport --import [FILE] [--mode <MODE>] [--verify <PUBKEY>] [--force] [--dry-run]
+\tgexport --export-bundle [NAME]... --sign <KEY>
+\tgexport --gen-key <FILE>
 \tgexport --init <SHELL>
-\tgexport --clear
+\tgexport --completions <SHELL>
+\tgexport --render <SHELL>
+\tgexport --clear [--force] [--dry-run]
+\tgexport --recompute
+\tgexport edit [--force]
+\tgexport diff [NAME]...
+\tgexport which <NAME>
+\tgexport doctor
+\tgexport sync <init <REMOTE>|push|pull>
 ")]
+#[command(args_conflicts_with_subcommands = true)]
 pub(crate) struct Cli {
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+
     #[command(flatten)]
     pub(crate) args: Option<GexportArgs>,
 
     /// Delete all stored variables.
     #[arg(long)]
-    #[arg(conflicts_with_all = ["print", "import", "init"])]
+    #[arg(conflicts_with_all = ["print", "import", "init", "export_bundle", "gen_key", "recompute", "completions", "render"])]
     #[arg(verbatim_doc_comment)]
     pub(crate) clear: bool,
 
+    /// Reruns the commands of stale computed variables, see --command.
+    /// 
+    /// Note: This is done automatically by the shell hook.
+    #[arg(long)]
+    #[arg(conflicts_with_all = ["print", "import", "init", "clear", "export_bundle", "gen_key", "completions", "render"])]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) recompute: bool,
+
     /// Prints all the globally exported environment variables. 
     /// 
     /// This is the default action if no other arguments are provided. Optionally a list 
     /// of identifiers can be given to select which environment variables are shown.
+    /// 
+    /// Example: gexport -p 'AWS_*'
     #[arg(short, long)]
     #[arg(value_name = "NAME")]
     #[arg(num_args = 0..)]
-    #[arg(conflicts_with_all = ["init", "import", "clear"])]
+    #[arg(group = "selector")]
+    #[arg(add = ArgValueCompleter::new(completions::stored_names))]
+    #[arg(conflicts_with_all = ["init", "import", "clear", "export_bundle", "gen_key", "recompute", "completions", "render"])]
     #[arg(verbatim_doc_comment)]
     pub(crate) print: Option<Vec<OsString>>,
 
+    /// Selects variables for -p, -d and -u by regular expressions instead of names.
+    /// 
+    /// Without it names may be glob patterns containing * and ?.
+    /// 
+    /// Example: gexport -d --regex '^TF_VAR_'
+    #[arg(long)]
+    #[arg(requires = "selector")]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) regex: bool,
+
+    /// Prints the description, tags and owner of each variable below it.
+    #[arg(long)]
+    #[arg(conflicts_with_all = ["init", "import", "clear", "export_bundle", "gen_key", "recompute", "completions", "render"])]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) long: bool,
+
+    /// Prints the variables of each layer the calling shell sources, in order of increasing
+    /// precedence: those of the system store, the global variables, then those of the tmux
+    /// session, screen session and terminal, see --system and --scope.
+    /// 
+    /// Example: gexport -p --layers 'AWS_*'
+    #[arg(long)]
+    #[arg(conflicts_with_all = ["init", "import", "clear", "export_bundle", "gen_key", "recompute", "completions", "render", "scope", "system"])]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) layers: bool,
+
+    /// Tags the exported variables, or only prints variables with all of the given tags.
+    /// 
+    /// Tags given on export replace the stored ones, they may contain letters, digits, _, . and -.
+    /// 
+    /// Examples: gexport --tag network --tag corp HTTP_PROXY=http://proxy:3128
+    ///           gexport -p --tag network
+    #[arg(long)]
+    #[arg(value_name = "TAG")]
+    #[arg(value_parser = parse_tag)]
+    #[arg(conflicts_with_all = ["init", "import", "clear", "export_bundle", "gen_key", "recompute", "completions", "render", "delete"])]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) tag: Vec<String>,
+
+    /// Prints the changes to the stored variables instead of storing them.
+    /// 
+    /// Applies to exports, --import and --clear, e.g. to check which variables a pattern selects.
+    /// 
+    /// Example: gexport --import vars.txt --mode replace --dry-run
+    #[arg(long)]
+    #[arg(conflicts_with_all = ["print", "init", "export_bundle", "gen_key", "recompute", "completions", "render"])]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) dry_run: bool,
+
+    /// Changes or deletes the environment variables even if they are locked by --readonly.
+    /// 
+    /// Applies to exports, --import, --clear and edit.
+    #[arg(long)]
+    #[arg(global = true)]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) force: bool,
+
     /// Import environment variables from file or stdin. For expected format see -p / --print.
     /// 
+    /// Note: Lines not in this format are rejected, in which case nothing is imported.
+    /// 
     /// Examples: gexport -p VAR1 VAR2 | ssh user@example 'gexport --import'
     ///           gexport --import vars.txt
     #[arg(long)]
     #[arg(value_name = "FILE")]
     #[arg(default_missing_value = "-")]
     #[arg(num_args = 0..=1)]
-    #[arg(conflicts_with_all = ["print", "init", "clear"])]
+    #[arg(conflicts_with_all = ["print", "init", "clear", "export_bundle", "gen_key", "recompute", "completions", "render"])]
     #[arg(verbatim_doc_comment)]
     pub(crate) import: Option<FileOrStdin>,
+
+    /// How imported environment variables are reconciled with the stored ones.
+    #[arg(long)]
+    #[arg(value_name = "MODE")]
+    #[arg(value_enum)]
+    #[arg(default_value_t = ImportMode::Merge)]
+    #[arg(requires = "import")]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) mode: ImportMode,
+
+    /// Only import bundles signed by one of the public keys listed in the given file.
+    /// 
+    /// Bundles no newer than the last one imported from the same key are rejected, so a captured
+    /// bundle cannot be replayed to roll values back.
+    /// 
+    /// Example: command="gexport --import --verify ~/.config/gexport/trusted" in authorized_keys
+    #[arg(long)]
+    #[arg(value_name = "PUBKEY")]
+    #[arg(requires = "import")]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) verify: Option<PathBuf>,
+
+    /// Prints a signed bundle of the globally exported environment variables for --import.
+    /// 
+    /// Optionally a list of identifiers can be given to select which environment variables
+    /// are bundled.
+    /// 
+    /// Example: gexport --export-bundle VAR --sign key | ssh user@example 'gexport --import --verify trusted'
+    #[arg(long)]
+    #[arg(value_name = "NAME")]
+    #[arg(num_args = 0..)]
+    #[arg(requires = "sign")]
+    #[arg(add = ArgValueCompleter::new(completions::stored_names))]
+    #[arg(conflicts_with_all = ["print", "init", "import", "clear", "gen_key", "recompute", "completions", "render"])]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) export_bundle: Option<Vec<OsString>>,
+
+    /// Secret key used to sign the bundle, see --gen-key.
+    #[arg(long)]
+    #[arg(value_name = "KEY")]
+    #[arg(requires = "export_bundle")]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) sign: Option<PathBuf>,
+
+    /// Generates a key pair for signing bundles, the public key is written to FILE.pub.
+    #[arg(long)]
+    #[arg(value_name = "FILE")]
+    #[arg(conflicts_with_all = ["print", "init", "import", "clear", "export_bundle", "recompute", "completions", "render"])]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) gen_key: Option<PathBuf>,
     
     /// Bash: echo 'eval "$(gexport --init bash)"' >> ~/.bashrc
     ///  Zsh: echo 'eval "$(gexport --init zsh)"' >> ~/.zshrc
+    ///   Sh: echo 'eval "$(gexport --init sh)"' >> ~/.shrc, with ENV=~/.shrc set in ~/.profile
+    ///   Nu: gexport --init nu | save -f ~/.config/nushell/gexport.nu, then add
+    ///       'source gexport.nu' to config.nu
+    /// Pwsh: Add-Content $PROFILE 'Invoke-Expression (& gexport --init pwsh | Out-String)'
+    /// Tcsh: gexport --init tcsh > ~/.gexport.tcsh; echo 'source ~/.gexport.tcsh' >> ~/.tcshrc
+    /// Elvish: echo 'eval (gexport --init elvish | slurp)' >> ~/.config/elvish/rc.elv
+    /// Xonsh: echo 'execx($(gexport --init xonsh))' >> ~/.xonshrc
     #[arg(long)]
     #[arg(value_name = "SHELL")]
     #[arg(value_enum)]
-    #[arg(conflicts_with_all = ["print", "import", "clear"])]
+    #[arg(conflicts_with_all = ["print", "import", "clear", "export_bundle", "gen_key", "recompute", "completions", "render"])]
     #[arg(verbatim_doc_comment)]
     pub(crate) init: Option<Shell>,
+
+    /// Prints the completion script for the given shell, including the names of the stored variables.
+    /// 
+    /// Bash: echo 'eval "$(gexport --completions bash)"' >> ~/.bashrc
+    ///  Zsh: echo 'eval "$(gexport --completions zsh)"' >> ~/.zshrc
+    /// Fish: echo 'gexport --completions fish | source' >> ~/.config/fish/config.fish
+    #[arg(long)]
+    #[arg(value_name = "SHELL")]
+    #[arg(value_enum)]
+    #[arg(conflicts_with_all = ["print", "import", "init", "clear", "export_bundle", "gen_key", "recompute", "render"])]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) completions: Option<CompletionShell>,
+
+    /// Prints the stored variables which apply to the calling shell as commands of the given shell.
+    /// 
+    /// Used by the hooks of shells which cannot source the stored variables themselves.
+    #[arg(long)]
+    #[arg(value_name = "SHELL")]
+    #[arg(value_enum)]
+    #[arg(conflicts_with_all = ["print", "import", "init", "clear", "export_bundle", "gen_key", "recompute", "completions", "scope"])]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) render: Option<Shell>,
     
+    /// Stores the variables only for the shells in the same tmux session, screen session or
+    /// terminal, instead of all shells.
+    /// 
+    /// Scoped variables take precedence over the global ones, the other options apply to the
+    /// variables of the given scope, e.g. -p --scope tmux prints them. Those of a terminal are
+    /// dropped once its session ends.
+    /// 
+    /// Example: gexport --scope tmux PROJECT=foo
+    #[arg(long)]
+    #[arg(value_name = "SCOPE")]
+    #[arg(value_enum)]
+    #[arg(conflicts_with_all = ["init", "export_bundle", "gen_key", "recompute", "completions", "render", "command", "builtin"])]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) scope: Option<Scope>,
+
+    /// Stores the variables in the system store sourced by the shells of all users, which requires
+    /// write access to it.
+    /// 
+    /// Users override the variables of the system store with their own, unless they are marked
+    /// --readonly. The system store is the first gexport/gexports in $XDG_CONFIG_DIRS, otherwise
+    /// /etc/gexport/gexports. Shells pick up changes to it once the variables of the user change.
+    /// 
+    /// Example: sudo gexport --system --readonly HTTP_PROXY=http://proxy:3128
+    #[arg(long)]
+    #[arg(conflicts_with_all = ["init", "export_bundle", "gen_key", "recompute", "completions", "render", "scope", "command", "builtin"])]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) system: bool,
+
+    /// Prints the env-var files of the scopes the calling shell is in, used by the shell hook.
+    #[arg(long)]
+    #[arg(hide = true)]
+    #[arg(conflicts_with_all = ["print", "import", "init", "clear", "export_bundle", "gen_key", "recompute", "completions", "render", "scope"])]
+    pub(crate) scope_files: bool,
+
+    /// Prints the commands recording the values the stored variables had before gexport first set
+    /// them and restoring those of deleted ones, used by the shell hook.
+    #[arg(long)]
+    #[arg(hide = true)]
+    #[arg(conflicts_with_all = ["print", "import", "init", "clear", "export_bundle", "gen_key", "recompute", "completions", "render", "scope", "system", "scope_files"])]
+    pub(crate) tombstones: bool,
+
+    /// Prints the location of the system store, used by the shell hook.
+    #[arg(long)]
+    #[arg(hide = true)]
+    #[arg(conflicts_with_all = ["print", "import", "init", "clear", "export_bundle", "gen_key", "recompute", "completions", "render", "scope", "system", "scope_files"])]
+    pub(crate) system_file: bool,
+
     #[arg(long)]
     #[arg(hide = true)]
     #[arg(env = "XDG_CONFIG_HOME")]
@@ -64,28 +281,130 @@ pub(crate) struct Cli {
     pub(crate) config_home: PathBuf,
 }
 
+#[derive(Subcommand)]
+pub(crate) enum Command {
+    /// Edit the stored environment variables in $VISUAL or $EDITOR.
+    /// 
+    /// Changes are validated and shown as diff before they are stored, the edit is rejected if
+    /// the stored variables were changed meanwhile.
+    #[command(verbatim_doc_comment)]
+    Edit,
+
+    /// Compare the stored environment variables with the environment of the current shell.
+    /// 
+    /// Reports stored variables which are missing from the environment or have a different value,
+    /// e.g. because they were overridden in the shell. Exits with 1 if there are any.
+    #[command(verbatim_doc_comment)]
+    Diff {
+        #[arg(value_name = "NAME")]
+        #[arg(add = ArgValueCompleter::new(completions::stored_names))]
+        names: Vec<OsString>,
+    },
+
+    /// Print the value of a variable in the calling shell and the layer it comes from.
+    /// 
+    /// Variables of the tmux session, screen session and terminal override the global ones,
+    /// the overridden layers are listed as well, see --scope and -p --layers.
+    #[command(verbatim_doc_comment)]
+    Which {
+        #[arg(value_name = "NAME")]
+        #[arg(add = ArgValueCompleter::new(completions::stored_names))]
+        name: OsString,
+    },
+
+    /// Check the setup of gexport and its hook in the current shell.
+    /// 
+    /// Each problem found is reported with a fix. Exits with 1 if there are any.
+    #[command(verbatim_doc_comment)]
+    Doctor,
+
+    /// Synchronize the stored environment variables across machines using git.
+    #[command(subcommand)]
+    Sync(SyncCommand),
+}
+
+#[derive(Subcommand)]
+pub(crate) enum SyncCommand {
+    /// Track the stored environment variables in a local git repository synced with REMOTE.
+    /// 
+    /// Example: gexport sync init git@example.com:user/gexports.git
+    #[command(verbatim_doc_comment)]
+    Init {
+        #[arg(value_name = "REMOTE")]
+        remote: OsString,
+    },
+
+    /// Merge remote changes and push the stored environment variables to the remote.
+    Push,
+
+    /// Merge remote changes into the stored environment variables.
+    /// 
+    /// Changes are merged per variable, only concurrent changes to the same variable conflict,
+    /// in which case the local value is kept.
+    #[command(verbatim_doc_comment)]
+    Pull,
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum)]
 pub(crate) enum Shell {
     Bash,
     Zsh,
+    Sh,
+    #[value(name = "nu")]
+    Nushell,
+    #[value(name = "pwsh")]
+    Powershell,
+    #[value(alias = "csh")]
+    Tcsh,
+    Elvish,
+    Xonsh,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum)]
+pub(crate) enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Scopes in order of increasing precedence, the global scope coming first.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum)]
+pub(crate) enum Scope {
+    Tmux,
+    Screen,
+    Tty,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum)]
+pub(crate) enum ImportMode {
+    /// Delete all stored variables before importing.
+    Replace,
+    /// Overwrite stored variables with the imported ones.
+    Merge,
+    /// Skip imported variables which are already stored.
+    Keep,
+    /// Ask before overwriting a stored variable with a different value.
+    Interactive,
 }
 
 #[derive(Args)]
-#[group(conflicts_with_all = ["init", "print", "import", "clear"])]
+#[group(conflicts_with_all = ["init", "print", "long", "layers", "import", "clear", "export_bundle", "gen_key", "recompute", "completions", "render", "scope_files", "system_file", "tombstones"])]
 pub(crate) struct GexportArgs {
     /// Environment variables to be globally exported.
     /// 
     /// Note: If no value is given tries to globally reexport, this requires the environment
     /// variable to already be shell exported, otherwise will set to empty string.
     #[arg(value_name = "NAME[=VALUE]")]
+    #[arg(add = ArgValueCompleter::new(completions::variable_names))]
     #[arg(verbatim_doc_comment)]
     pub(crate) vars: Vec<OsString>,
 
-    /// Unsets the given environment variables.
+    /// Unsets the given environment variables, patterns select from the current environment.
     ///
     /// Note: This will continuously clear the environment variables as the shells sources
     /// gexport's env-var files.
     #[arg(short, long)]
+    #[arg(group = "selector")]
     #[arg(conflicts_with_all = ["delete", "unexport"])]
     #[arg(verbatim_doc_comment)]
     pub(crate) unset: bool,
@@ -98,14 +417,132 @@ pub(crate) struct GexportArgs {
     #[arg(verbatim_doc_comment)]
     pub(crate) unexport: bool,
     
-    /// Delete the specified environment variables.
+    /// Delete the specified environment variables, all variants unless --when is given.
     /// 
-    /// Note: This deletes the variable from gexport's env-var files, causing all open shell sessions
-    /// to indefinitely maintain the value until manually unset or restarted.
+    /// Note: Open shell sessions restore the value the variable had before gexport first set it,
+    /// or unset it if it had none. Read-only variables keep their value until the shell restarts.
     #[arg(short, long)]
+    #[arg(group = "selector")]
     #[arg(conflicts_with_all = ["unset", "unexport"])]
     #[arg(verbatim_doc_comment)]
     pub(crate) delete: bool,
+
+    /// Marks the environment variables as read-only in the shells and locks them in the store.
+    /// 
+    /// Locked variables are only changed or deleted with --force. Shells that already have the
+    /// variable keep its value until they are restarted, as read-only variables cannot be changed.
+    /// 
+    /// Example: gexport --readonly KUBECONFIG=/etc/kube/prod.yaml
+    #[arg(short, long)]
+    #[arg(conflicts_with_all = ["delete", "unset", "unexport", "template", "command", "builtin"])]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) readonly: bool,
+
+    /// Only allows integers as values, shells evaluate values assigned to them arithmetically.
+    /// 
+    /// Example: gexport --integer RETRIES=3
+    #[arg(long)]
+    #[arg(conflicts_with_all = ["delete", "unset", "template", "command", "builtin", "lower", "upper"])]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) integer: bool,
+
+    /// Converts the values to lowercase, also when assigned to in the shells.
+    #[arg(long)]
+    #[arg(conflicts_with_all = ["delete", "unset", "template", "command", "builtin", "integer", "upper"])]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) lower: bool,
+
+    /// Converts the values to uppercase, also when assigned to in the shells.
+    #[arg(long)]
+    #[arg(conflicts_with_all = ["delete", "unset", "template", "command", "builtin", "integer", "lower"])]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) upper: bool,
+
+    /// Describes what the environment variables are for, shown by -p --long.
+    /// 
+    /// Example: gexport --describe 'proxy for corp VPN' HTTP_PROXY=http://proxy:3128
+    #[arg(long)]
+    #[arg(value_name = "TEXT")]
+    #[arg(conflicts_with = "delete")]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) describe: Option<String>,
+
+    /// Only apply the environment variables in shells matching the condition.
+    /// 
+    /// Keys are host, shell (bash, zsh) and os (linux, darwin, ...), patterns may contain the
+    /// wildcards * and ?. Variants of a variable with different conditions are stored side by
+    /// side, where they apply they take precedence over the value without conditions.
+    /// 
+    /// Example: gexport --when host=build-* JAVA_HOME=/opt/jdk21
+    #[arg(long)]
+    #[arg(value_name = "KEY=PATTERN")]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) when: Vec<Condition>,
+
+    /// Stores the values as templates which may reference other variables as $NAME or ${NAME}.
+    /// 
+    /// References are resolved in each shell and updated whenever the stored variables change.
+    /// Use \$ for a literal $.
+    /// 
+    /// Example: gexport --template 'KUBECONFIG=$HOME/.kube/$CLUSTER'
+    #[arg(short, long)]
+    #[arg(conflicts_with_all = ["delete", "unset"])]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) template: bool,
+
+    /// Runs the values as shell commands and stores their output instead.
+    /// 
+    /// The output is cached and shared by all shells, use --refresh to rerun the command
+    /// once the cached output is older than the given duration.
+    /// 
+    /// Example: gexport --command 'GIT_ROOT=git rev-parse --show-toplevel' --refresh 5m
+    #[arg(short, long)]
+    #[arg(group = "producer")]
+    #[arg(conflicts_with_all = ["delete", "unset", "template", "builtin"])]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) command: bool,
+
+    /// Like --command, but the values name one of gexport's builtin producers.
+    /// 
+    /// Builtins: ssh-auth-sock
+    /// 
+    /// Example: gexport --builtin SSH_AUTH_SOCK=ssh-auth-sock --refresh 1m
+    #[arg(long)]
+    #[arg(group = "producer")]
+    #[arg(conflicts_with_all = ["delete", "unset", "template", "command"])]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) builtin: bool,
+
+    /// Duration after which computed values are recomputed, e.g. 30s, 5m, 1h or 1d.
+    #[arg(long)]
+    #[arg(value_name = "DURATION")]
+    #[arg(value_parser = parse_duration)]
+    #[arg(requires = "producer")]
+    #[arg(verbatim_doc_comment)]
+    pub(crate) refresh: Option<u64>,
+}
+
+pub(crate) fn parse_duration(input: &str) -> Result<u64, String> {
+    let (number, unit) = input.split_at(input.find(|c: char| !c.is_ascii_digit()).unwrap_or(input.len()));
+    let factor = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("unknown unit '{unit}', expected s, m, h or d")),
+    };
+    let number = number.parse::<u64>()
+        .map_err(|_| str!("expected a number followed by s, m, h or d"))?;
+    number.checked_mul(factor)
+        .ok_or_else(|| format!("'{input}' is too long a duration"))
+}
+
+fn parse_tag(input: &str) -> Result<String, String> {
+    if !input.is_empty() && input.chars().all(|c| c.is_ascii_alphanumeric() || "_.-".contains(c)) {
+        Ok(input.to_string())
+    } else {
+        Err(str!("expected letters, digits, _, . or -"))
+    }
 }
 
 const HEADER: Style = AnsiColor::Green.on_default().effects(Effects::BOLD);
@@ -123,4 +560,24 @@ const STYLE: Styles = Styles::styled()
     .placeholder(PLACEHOLDER)
     .error(ERROR)
     .valid(VALID)
-    .invalid(INVALID);
\ No newline at end of file
+    .invalid(INVALID);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90"), Ok(90));
+        assert_eq!(parse_duration("15m"), Ok(15 * 60));
+        assert_eq!(parse_duration("2d"), Ok(2 * 24 * 60 * 60));
+        assert!(parse_duration("1w").is_err());
+        assert!(parse_duration("m").is_err());
+    }
+
+    #[test]
+    fn rejects_overflowing_durations() {
+        assert!(parse_duration(&format!("{}d", u64::MAX)).is_err());
+        assert!(parse_duration("99999999999999999999").is_err());
+    }
+}
@@ -0,0 +1,340 @@
+use crate::str;
+use crate::utils::{hostname, now, owner};
+use std::error::Error;
+use std::fmt;
+use std::fmt::Display;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use ed25519_dalek::{PUBLIC_KEY_LENGTH, SECRET_KEY_LENGTH, SIGNATURE_LENGTH};
+
+const HEADER: &str = "# gexport bundle";
+const ORIGIN: &str = "# origin: ";
+const TIMESTAMP: &str = "# timestamp: ";
+const KEY: &str = "# key: ";
+const SIGNATURE: &str = "# signature: ";
+
+/// Wraps the given lines into a bundle signed with `key`.
+///
+/// The bundle carries its origin host, the time of creation and the public key of the signer, the
+/// signature covers everything preceding it.
+pub(crate) fn create(lines: &[String], key: &SigningKey) -> String {
+    let timestamp = now();
+
+    let mut bundle = format!(
+        "{HEADER}\n{ORIGIN}{}\n{TIMESTAMP}{timestamp}\n{KEY}{}\n",
+        hostname(),
+        hex_encode(key.verifying_key().as_bytes()),
+    );
+    lines.iter()
+        .for_each(|line| {
+            bundle.push_str(line);
+            bundle.push('\n');
+        });
+
+    let signature = key.sign(bundle.as_bytes());
+    bundle.push_str(&format!("{SIGNATURE}{}\n", hex_encode(&signature.to_bytes())));
+    bundle
+}
+
+pub(crate) fn is_bundle(data: &[u8]) -> bool {
+    data.split(|&byte| byte == b'\n')
+        .next()
+        .is_some_and(|line| line == HEADER.as_bytes())
+}
+
+/// Contents of an opened bundle.
+pub(crate) struct Bundle {
+    pub(crate) declarations: Vec<u8>,
+    key: VerifyingKey,
+    timestamp: u64,
+}
+
+/// Opens a bundle.
+///
+/// If `trusted` is given the bundle has to be signed by one of those keys, otherwise the signature
+/// is not checked.
+pub(crate) fn open(data: &[u8], trusted: Option<&[VerifyingKey]>) -> Result<Bundle, BundleError> {
+    if !is_bundle(data) {
+        return Err(BundleError::NotABundle);
+    }
+
+    let data = str::from_utf8(data).map_err(|_| BundleError::Malformed)?;
+    let content = data.strip_suffix('\n').unwrap_or(data);
+    let (signed, signature) = content
+        .rsplit_once('\n')
+        .and_then(|(signed, signature)| {
+            Some((&data[..signed.len() + 1], signature.strip_prefix(SIGNATURE)?))
+        })
+        .ok_or(BundleError::Malformed)?;
+
+    let mut lines = signed.lines().skip(1);
+    let mut header = |prefix: &str| {
+        lines.next()
+            .and_then(|line| line.strip_prefix(prefix))
+            .ok_or(BundleError::Malformed)
+    };
+    header(ORIGIN)?;
+    let timestamp = header(TIMESTAMP)?.parse::<u64>().map_err(|_| BundleError::Malformed)?;
+    let key = hex_decode::<PUBLIC_KEY_LENGTH>(header(KEY)?)
+        .and_then(|key| VerifyingKey::from_bytes(&key).ok())
+        .ok_or(BundleError::Malformed)?;
+
+    if let Some(trusted) = trusted {
+        if !trusted.contains(&key) {
+            return Err(BundleError::UntrustedKey);
+        }
+
+        let signature = hex_decode::<SIGNATURE_LENGTH>(signature)
+            .map(|signature| Signature::from_bytes(&signature))
+            .ok_or(BundleError::Malformed)?;
+        key.verify_strict(signed.as_bytes(), &signature)
+            .map_err(|_| BundleError::InvalidSignature)?;
+    }
+
+    let declarations = signed
+        .lines()
+        .skip(4)
+        .flat_map(|line| [line, "\n"])
+        .collect::<String>();
+    Ok(Bundle {
+        declarations: declarations.into_bytes(),
+        key,
+        timestamp,
+    })
+}
+
+/// Rejects bundles no newer than the last one imported from the same key, listed in the `history`
+/// file, so a captured bundle cannot be replayed to roll values back.
+pub(crate) fn check_replay(bundle: &Bundle, history: &Path) -> Result<(), BundleError> {
+    match last_imported(history, &bundle.key)? {
+        Some(last) if bundle.timestamp <= last => Err(BundleError::Replayed),
+        _ => Ok(()),
+    }
+}
+
+/// Records the bundle as the last one imported from its key in the `history` file.
+pub(crate) fn record_import(bundle: &Bundle, history: &Path) -> Result<(), BundleError> {
+    let key = hex_encode(bundle.key.as_bytes());
+    let mut lines = read_history(history)?
+        .into_iter()
+        .filter(|line| !line.starts_with(&key))
+        .collect::<Vec<_>>();
+    lines.push(format!("{key} {}", bundle.timestamp));
+    fs::write(history, lines.iter().map(|line| format!("{line}\n")).collect::<String>())?;
+    Ok(())
+}
+
+fn last_imported(history: &Path, key: &VerifyingKey) -> Result<Option<u64>, BundleError> {
+    let key = hex_encode(key.as_bytes());
+    Ok(read_history(history)?
+        .iter()
+        .filter_map(|line| line.split_once(' '))
+        .find(|(other, _)| *other == key)
+        .and_then(|(_, timestamp)| timestamp.parse().ok()))
+}
+
+fn read_history(history: &Path) -> Result<Vec<String>, BundleError> {
+    match fs::read_to_string(history) {
+        Ok(content) => Ok(content.lines().map(str::to_string).collect()),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Generates a new key pair, the secret key is written to `path` and the public key to `path.pub`.
+pub(crate) fn generate_key(path: &Path) -> Result<(), BundleError> {
+    let mut seed = [0u8; SECRET_KEY_LENGTH];
+    fs::File::open("/dev/urandom")?.read_exact(&mut seed)?;
+    let key = SigningKey::from_bytes(&seed);
+
+    let mut secret = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    writeln!(secret, "{}", hex_encode(key.as_bytes()))?;
+
+    let mut public = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(public_key_path(path))?;
+    writeln!(public, "{} {}", hex_encode(key.verifying_key().as_bytes()), owner())?;
+
+    Ok(())
+}
+
+pub(crate) fn read_signing_key(path: &Path) -> Result<SigningKey, BundleError> {
+    fs::read_to_string(path)?
+        .split_whitespace()
+        .next()
+        .and_then(hex_decode::<SECRET_KEY_LENGTH>)
+        .map(|key| SigningKey::from_bytes(&key))
+        .ok_or_else(|| BundleError::InvalidKey(path.to_path_buf()))
+}
+
+/// Reads a list of public keys, one per line optionally followed by a comment.
+pub(crate) fn read_verifying_keys(path: &Path) -> Result<Vec<VerifyingKey>, BundleError> {
+    fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            line.split_whitespace()
+                .next()
+                .and_then(hex_decode::<PUBLIC_KEY_LENGTH>)
+                .and_then(|key| VerifyingKey::from_bytes(&key).ok())
+                .ok_or_else(|| BundleError::InvalidKey(path.to_path_buf()))
+        })
+        .collect()
+}
+
+fn public_key_path(path: &Path) -> PathBuf {
+    let mut public = path.as_os_str().to_owned();
+    public.push(".pub");
+    PathBuf::from(public)
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn hex_decode<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    if hex.len() != N * 2 || !hex.is_ascii() {
+        return None;
+    }
+
+    let mut bytes = [0u8; N];
+    for (index, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+#[derive(Debug)]
+pub(crate) enum BundleError {
+    Io(ErrorKind),
+    InvalidKey(PathBuf),
+    NotABundle,
+    Malformed,
+    UntrustedKey,
+    InvalidSignature,
+    Replayed,
+}
+
+impl From<io::Error> for BundleError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error.kind())
+    }
+}
+
+impl Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let error = match self {
+            Self::Io(ErrorKind::NotFound) => str!("key file not found"),
+            Self::Io(ErrorKind::AlreadyExists) => str!("key file already exists"),
+            Self::Io(ErrorKind::PermissionDenied) => str!("permission denied"),
+            Self::Io(error) => format!("{error:?}"),
+            Self::InvalidKey(path) => format!("'{}' does not contain a valid key", path.display()),
+            Self::NotABundle => str!("import is not a bundle"),
+            Self::Malformed => str!("bundle is malformed"),
+            Self::UntrustedKey => str!("bundle is not signed by a trusted key"),
+            Self::InvalidSignature => str!("bundle signature is invalid"),
+            Self::Replayed => str!("bundle is not newer than the last one imported from the same key"),
+        };
+        write!(f, "{error}")
+    }
+}
+
+impl Error for BundleError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; SECRET_KEY_LENGTH])
+    }
+
+    fn lines() -> Vec<String> {
+        vec![str!("declare -gx A='1'"), str!("unset B")]
+    }
+
+    #[test]
+    fn opens_bundles_of_trusted_keys() {
+        let bundle = create(&lines(), &key(1));
+        let trusted = [key(2).verifying_key(), key(1).verifying_key()];
+        let bundle = open(bundle.as_bytes(), Some(&trusted)).unwrap();
+        assert_eq!(bundle.declarations, b"declare -gx A='1'\nunset B\n");
+    }
+
+    #[test]
+    fn opens_unverified_bundles_without_trusted_keys() {
+        let bundle = create(&lines(), &key(1));
+        assert!(open(bundle.as_bytes(), None).is_ok());
+    }
+
+    #[test]
+    fn rejects_untrusted_keys() {
+        let bundle = create(&lines(), &key(1));
+        let trusted = [key(2).verifying_key()];
+        assert!(matches!(open(bundle.as_bytes(), Some(&trusted)), Err(BundleError::UntrustedKey)));
+    }
+
+    #[test]
+    fn rejects_tampered_bundles() {
+        let bundle = create(&lines(), &key(1));
+        let trusted = [key(1).verifying_key()];
+
+        let tampered = bundle.replace("A='1'", "A='2'");
+        assert!(matches!(open(tampered.as_bytes(), Some(&trusted)), Err(BundleError::InvalidSignature)));
+
+        let (signed, signature) = bundle.trim_end().rsplit_once('\n').unwrap();
+        let appended = format!("{signed}\ndeclare -gx C='3'\n{signature}\n");
+        assert!(matches!(open(appended.as_bytes(), Some(&trusted)), Err(BundleError::InvalidSignature)));
+
+        // swapping in another trusted key does not make the signature of the original one valid
+        let other = bundle.replace(
+            &hex_encode(key(1).verifying_key().as_bytes()),
+            &hex_encode(key(2).verifying_key().as_bytes()),
+        );
+        let trusted = [key(1).verifying_key(), key(2).verifying_key()];
+        assert!(matches!(open(other.as_bytes(), Some(&trusted)), Err(BundleError::InvalidSignature)));
+    }
+
+    #[test]
+    fn rejects_malformed_bundles() {
+        let trusted = [key(1).verifying_key()];
+        let bundle = create(&lines(), &key(1));
+        let (unsigned, _) = bundle.trim_end().rsplit_once('\n').unwrap();
+
+        assert!(matches!(open(b"declare -gx A='1'\n", Some(&trusted)), Err(BundleError::NotABundle)));
+        assert!(matches!(open(unsigned.as_bytes(), Some(&trusted)), Err(BundleError::Malformed)));
+        assert!(matches!(open(format!("{HEADER}\n").as_bytes(), None), Err(BundleError::Malformed)));
+    }
+
+    #[test]
+    fn rejects_replayed_bundles() {
+        let history = env::temp_dir().join(format!("gexport-test-history-{}", std::process::id()));
+        let opened = |timestamp: u64| Bundle { declarations: Vec::new(), key: key(1).verifying_key(), timestamp };
+
+        check_replay(&opened(100), &history).unwrap();
+        record_import(&opened(100), &history).unwrap();
+        record_import(&Bundle { key: key(2).verifying_key(), ..opened(500) }, &history).unwrap();
+
+        assert!(matches!(check_replay(&opened(99), &history), Err(BundleError::Replayed)));
+        assert!(matches!(check_replay(&opened(100), &history), Err(BundleError::Replayed)));
+        assert!(check_replay(&opened(101), &history).is_ok());
+        record_import(&opened(200), &history).unwrap();
+        assert!(matches!(check_replay(&opened(150), &history), Err(BundleError::Replayed)));
+
+        fs::remove_file(&history).unwrap();
+    }
+}
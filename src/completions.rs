@@ -0,0 +1,95 @@
+use crate::args::{Cli, CompletionShell};
+use crate::utils::get_default_config_dir;
+use crate::env_vars::EnvironmentVariable;
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use clap::{CommandFactory, ValueEnum};
+use clap_complete::CompletionCandidate;
+use clap_complete::env::{CompleteEnv, Shells};
+use itertools::Itertools;
+
+/// Name of the variable the registration scripts set when asking gexport for completions.
+const VAR: &str = "COMPLETE";
+
+/// Answers completion requests from the shell and exits, has to run before anything is printed.
+pub(crate) fn complete() {
+    CompleteEnv::with_factory(Cli::command)
+        .var(VAR)
+        .complete();
+}
+
+/// Prints the script registering gexport's completions with the shell.
+pub(crate) fn print_registration(shell: CompletionShell) -> io::Result<()> {
+    let name = shell.to_possible_value().unwrap().get_name().to_string();
+    let shells = Shells::builtins();
+    let completer = shells.completer(&name).expect("builtin shell");
+    completer.write_registration(VAR, "gexport", "gexport", "gexport", &mut io::stdout())
+}
+
+/// Completes the names of the stored variables.
+pub(crate) fn stored_names(current: &OsStr) -> Vec<CompletionCandidate> {
+    candidates(current, stored())
+}
+
+/// Completes the names of the stored variables and those in the environment, only stored ones
+/// when deleting or unsetting.
+pub(crate) fn variable_names(current: &OsStr) -> Vec<CompletionCandidate> {
+    // the values belong to --print or --export-bundle, which complete them on their own
+    if given(Some('p'), "--print") || given(None, "--export-bundle") {
+        return Vec::new();
+    }
+    if given(Some('d'), "--delete") || given(Some('u'), "--unset") {
+        return stored_names(current);
+    }
+
+    let exported = env::vars_os()
+        .filter_map(|(name, _)| name.into_string().ok());
+    candidates(current, stored().into_iter().chain(exported))
+}
+
+fn candidates<I: IntoIterator<Item = String>>(current: &OsStr, names: I) -> Vec<CompletionCandidate> {
+    // nothing to complete once the value is reached
+    if current.as_bytes().contains(&b'=') {
+        return Vec::new();
+    }
+
+    let current = current.to_string_lossy();
+    names.into_iter()
+        .filter(|name| name.starts_with(current.as_ref()))
+        .unique()
+        .sorted()
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+fn stored() -> Vec<String> {
+    // the command line is incomplete and cannot be parsed, so --config-home is resolved manually
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(get_default_config_dir);
+    let data = fs::read(config_home.join("gexport").join("gexports")).unwrap_or_default();
+
+    String::from_utf8_lossy(&data)
+        .lines()
+        .filter_map(|line| EnvironmentVariable::from_line(line).ok())
+        .filter(|var| !var.is_internal())
+        .map(|var| var.name().to_string())
+        .collect()
+}
+
+/// Whether the command line being completed contains the flag, also within combined short flags.
+fn given(short: Option<char>, long: &str) -> bool {
+    env::args_os()
+        .skip_while(|arg| arg != "--")
+        .filter_map(|arg| arg.into_string().ok())
+        .any(|arg| {
+            arg == long
+                || short.is_some_and(|short| {
+                    arg.starts_with('-') && !arg.starts_with("--") && arg.contains(short)
+                })
+        })
+}
@@ -0,0 +1,100 @@
+use crate::set_failure;
+use crate::utils::mod_time;
+use crate::env_vars::{EncodeAction, EnvironmentVariable, Layer};
+use crate::env_vars::writer::{WriteManager, WriterError};
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::os::unix::ffi::OsStringExt;
+use colored::Colorize;
+
+/// How the calling shell's environment deviates from a stored variable.
+enum Deviation {
+    /// Stored as exported, but not in the environment.
+    Missing,
+    /// Different in the environment, while the shell has loaded the latest stored variables.
+    Overridden,
+    /// Different in the environment, the shell has not loaded the latest stored variables.
+    Differs,
+}
+
+/// Compares the stored variables which apply to the calling shell with its environment, only the
+/// given ones if any.
+pub(crate) fn diff(names: &[OsString]) -> Result<(), WriterError> {
+    let layers = Layer::all()?;
+
+    // the hook sources the store right before running a command, so a shell that has loaded the
+    // latest stored variables only deviates from them if they were changed in the shell itself
+    let modified = mod_time(&WriteManager::global_location()).map(|modified| modified.to_string());
+    let loaded = env::var("GEXPORT_MOD_TIME").ok();
+    let current = loaded.is_some() && loaded == modified;
+    if !current {
+        println!(
+            "{}",
+            "this shell has not loaded the latest stored variables, see 'gexport doctor'".yellow()
+        );
+    }
+
+    // the layers are sourced in order, the last applying line of a variable wins
+    let mut order = Vec::new();
+    let mut stored = HashMap::new();
+    let mut values = HashMap::new();
+    layers
+        .iter()
+        .flat_map(Layer::vars)
+        .filter(|var| !var.is_internal())
+        .for_each(|var| {
+            let value = (var.action() != EncodeAction::Unset).then(|| {
+                var.resolve(|name| {
+                    values.get(name)
+                        .cloned()
+                        .unwrap_or_else(|| env::var_os(name).map(OsString::into_vec))
+                })
+            });
+            if !stored.contains_key(var.name()) {
+                order.push(var.name().to_string());
+            }
+            values.insert(var.name().to_string(), value);
+            stored.insert(var.name().to_string(), var);
+        });
+
+    order.iter()
+        .filter(|name| names.is_empty() || names.iter().any(|selected| selected == name.as_str()))
+        .for_each(|name| {
+            let var = &stored[name];
+            let value = values[name].clone().unwrap_or_default();
+            let live = env::var_os(name).map(OsString::into_vec);
+
+            let deviation = match (var.action(), &live) {
+                (EncodeAction::Default | EncodeAction::Readonly, None) => Deviation::Missing,
+                (EncodeAction::Default | EncodeAction::Readonly, Some(live)) if *live == value => return,
+                // not exported variables are invisible to gexport, unless exported in the shell
+                (EncodeAction::Unexport | EncodeAction::Unset, None) => return,
+                _ if current => Deviation::Overridden,
+                _ => Deviation::Differs,
+            };
+
+            let status = match deviation {
+                Deviation::Missing => "is missing from the shell",
+                Deviation::Overridden => "is overridden in the shell",
+                Deviation::Differs => "differs from the shell",
+            };
+            println!("{} {status}", name.bold());
+            println!("{}", format!("- {}", display(name, value, var.action())).red());
+            if let Some(live) = live {
+                println!("{}", format!("+ {}", display(name, live, EncodeAction::Default)).green());
+            }
+            set_failure();
+        });
+
+    Ok(())
+}
+
+fn display(name: &str, value: Vec<u8>, action: EncodeAction) -> String {
+    let mut assignment = OsString::from(name);
+    assignment.push("=");
+    assignment.push(OsString::from_vec(value));
+    EnvironmentVariable::from_parse(&assignment)
+        .map(|var| var.with_action(action).friendly())
+        .unwrap_or_default()
+}
@@ -0,0 +1,223 @@
+use crate::set_failure;
+use crate::str;
+use crate::utils::{current_shell, mod_time};
+use crate::env_vars::{is_tombstone, EnvironmentVariable, Layer};
+use crate::env_vars::writer::WriteManager;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use colored::Colorize;
+
+/// Result of a single check, failures come with a fix.
+enum Outcome {
+    Pass(String),
+    Fail(String, String),
+    Skip,
+}
+
+/// Checks the setup of gexport and the hook in the calling shell, printing a fix for each problem.
+pub(crate) fn doctor() {
+    let shell = current_shell();
+    let store = WriteManager::store_location();
+    let hook = env::var_os("GEXPORT_SOURCE_FILE").map(PathBuf::from);
+
+    let outcomes = [
+        check_hook(&shell, hook.as_deref()),
+        check_preexec(&shell),
+        check_config_home(hook.as_deref(), &store),
+        check_loaded(hook.as_deref(), &store),
+        check_permissions(&WriteManager::directory(), true),
+        check_permissions(&store, false),
+        check_store(&store),
+        check_locked(),
+    ];
+
+    outcomes.into_iter()
+        .for_each(|outcome| {
+            match outcome {
+                Outcome::Pass(check) => println!("{} {check}", " ok ".green().bold()),
+                Outcome::Fail(problem, fix) => {
+                    println!("{} {problem}", "fail".red().bold());
+                    println!("     {} {fix}", "fix:".yellow());
+                    set_failure();
+                },
+                Outcome::Skip => {},
+            }
+        });
+}
+
+fn check_hook(shell: &str, hook: Option<&Path>) -> Outcome {
+    if hook.is_some() && env::var_os("GEXPORT_MOD_TIME").is_some() {
+        return Outcome::Pass(format!("hook is loaded in {shell}"));
+    }
+
+    let fix = match shell {
+        "bash" | "zsh" => {
+            format!("echo 'eval \"$(gexport --init {shell})\"' >> ~/.{shell}rc, then restart the shell")
+        },
+        "sh" | "dash" | "ash" => {
+            str!("echo 'eval \"$(gexport --init sh)\"' >> \"$ENV\", then restart the shell")
+        },
+        "nu" => {
+            str!("gexport --init nu | save -f ~/.config/nushell/gexport.nu, then add 'source gexport.nu' to config.nu")
+        },
+        "pwsh" => {
+            str!("Add-Content $PROFILE 'Invoke-Expression (& gexport --init pwsh | Out-String)', then restart the shell")
+        },
+        "tcsh" | "csh" => {
+            str!("gexport --init tcsh > ~/.gexport.tcsh; echo 'source ~/.gexport.tcsh' >> ~/.tcshrc, then restart the shell")
+        },
+        "elvish" => str!("echo 'eval (gexport --init elvish | slurp)' >> ~/.config/elvish/rc.elv, then restart the shell"),
+        "xonsh" => str!("echo 'execx($(gexport --init xonsh))' >> ~/.xonshrc, then restart the shell"),
+        _ => str!("run gexport from a shell with the hook set up, see --init"),
+    };
+    Outcome::Fail(format!("hook is not loaded in {shell}"), fix)
+}
+
+fn check_preexec(shell: &str) -> Outcome {
+    if shell != "bash" {
+        return Outcome::Skip;
+    }
+
+    // preexec functions are not inherited, ask a fresh interactive shell instead
+    let loaded = Command::new("bash")
+        .args(["-ic", "declare -F __bp_preexec_invoke_exec"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success());
+
+    if loaded {
+        Outcome::Pass(str!("bash-preexec is loaded"))
+    } else {
+        Outcome::Fail(
+            str!("bash-preexec is not loaded, the hook never runs"),
+            str!("install https://github.com/rcaloras/bash-preexec and source it in ~/.bashrc"),
+        )
+    }
+}
+
+fn check_config_home(hook: Option<&Path>, store: &Path) -> Outcome {
+    let Some(hook) = hook else {
+        return Outcome::Skip;
+    };
+
+    if hook == store {
+        Outcome::Pass(format!("hook and gexport use {}", store.display()))
+    } else {
+        Outcome::Fail(
+            format!("hook uses {}, but gexport uses {}", hook.display(), store.display()),
+            str!("set XDG_CONFIG_HOME before the init line in your rc file, then restart the shell"),
+        )
+    }
+}
+
+fn check_loaded(hook: Option<&Path>, store: &Path) -> Outcome {
+    let (Some(hook), Ok(loaded)) = (hook, env::var("GEXPORT_MOD_TIME")) else {
+        return Outcome::Skip;
+    };
+
+    if mod_time(hook).map(|modified| modified.to_string()).as_ref() == Some(&loaded) {
+        Outcome::Pass(str!("shell has loaded the latest stored variables"))
+    } else if hook != store {
+        Outcome::Skip
+    } else {
+        Outcome::Fail(
+            str!("shell has not loaded the latest stored variables"),
+            str!("make sure no other preexec hook fails before gexport's, or restart the shell"),
+        )
+    }
+}
+
+/// The env-var file is sourced by every shell, it must not be writable by anyone but the user.
+fn check_permissions(path: &Path, directory: bool) -> Outcome {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            return Outcome::Fail(
+                format!("{} does not exist", path.display()),
+                str!("store a variable or restart the shell to create it"),
+            );
+        },
+        Err(error) => {
+            return Outcome::Fail(
+                format!("cannot access {}, {:?}", path.display(), error.kind()),
+                format!("check the permissions of {} and its parents", path.display()),
+            );
+        },
+    };
+
+    // SAFETY: getuid has no preconditions and cannot fail
+    let user = unsafe { libc::getuid() };
+    let access = if directory { 0o700 } else { 0o600 };
+    if metadata.uid() != user {
+        Outcome::Fail(
+            format!("{} is not owned by the current user", path.display()),
+            format!("chown \"$USER\" {}", path.display()),
+        )
+    } else if metadata.mode() & 0o022 != 0 {
+        Outcome::Fail(
+            format!("{} is writable by other users", path.display()),
+            format!("chmod go-w {}", path.display()),
+        )
+    } else if metadata.mode() & access != access {
+        Outcome::Fail(
+            format!("{} is not accessible by the current user", path.display()),
+            format!("chmod u+{} {}", if directory { "rwx" } else { "rw" }, path.display()),
+        )
+    } else {
+        Outcome::Pass(format!("{} has the right permissions", path.display()))
+    }
+}
+
+fn check_store(store: &Path) -> Outcome {
+    let Ok(data) = fs::read(store) else {
+        return Outcome::Skip;
+    };
+
+    let rejected = String::from_utf8_lossy(&data)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !is_tombstone(line))
+        .filter(|(_, line)| EnvironmentVariable::from_line(line).is_err())
+        .map(|(index, _)| (index + 1).to_string())
+        .collect::<Vec<_>>();
+
+    if rejected.is_empty() {
+        Outcome::Pass(str!("stored variables parse"))
+    } else {
+        Outcome::Fail(
+            format!("stored variables do not parse, see line {}", rejected.join(", ")),
+            format!("correct or remove the lines in {}", store.display()),
+        )
+    }
+}
+
+fn check_locked() -> Outcome {
+    let Ok(layers) = Layer::all() else {
+        return Outcome::Skip;
+    };
+
+    let overriding = layers.iter()
+        .flat_map(|layer| {
+            layer.overriding_names()
+                .into_iter()
+                .map(move |name| (name, layer.name.as_str()))
+        })
+        .collect::<Vec<_>>();
+
+    match overriding.first() {
+        None => Outcome::Pass(str!("no variables override those locked by the system store")),
+        Some((name, layer)) => {
+            let scope = if *layer == "global" { String::new() } else { format!(" --scope {layer}") };
+            Outcome::Fail(
+                format!("'{name}' is locked by the system store, but stored in the {layer} layer"),
+                format!("gexport -d{scope} {name}"),
+            )
+        },
+    }
+}
@@ -0,0 +1,244 @@
+use crate::utils::{confirm, print_error};
+use crate::env_vars::{is_tombstone, Condition, EnvironmentVariable, ParserError};
+use crate::env_vars::writer::{WriteManager, WriterError};
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+use std::fmt::Display;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::process::Command;
+use colored::Colorize;
+use itertools::Itertools;
+
+const HEADER: &str = "\
+# Edit the stored environment variables, one NAME=value per line. Values are taken literally up to
+# the end of the line, except for the escapes \\n, \\t, \\xHH and \\\\.
+#
+# The flags -n, -u, -r, -t, -c, --integer, --lower, --upper, --builtin, --refresh DURATION and
+# --when KEY=PATTERN may precede a variable, same as on the command line. Lines starting with # are ignored.
+";
+
+/// Opens the stored variables in the user's editor and stores the result once it is valid.
+pub(crate) fn edit() -> Result<(), EditError> {
+    let stored = WriteManager::get()?
+        .iter()
+        .filter(|line| !line.trim().is_empty() && !is_tombstone(line))
+        .map(|line| EnvironmentVariable::from_line(line))
+        .filter_ok(|var| !var.is_internal())
+        .collect::<Result<Vec<_>, _>>()?;
+    let rendered = stored
+        .iter()
+        .map(EnvironmentVariable::friendly)
+        .collect::<Vec<_>>();
+
+    let file = TempFile::create()?;
+    writeln!(file.open()?, "{HEADER}\n{}", rendered.join("\n"))?;
+
+    let edited = loop {
+        run_editor(&file.path)?;
+        let content = fs::read(&file.path)?;
+        if let Some(edited) = parse(&String::from_utf8_lossy(&content), &stored, &rendered) {
+            break edited;
+        }
+        if !confirm("edit again? [y/N] ")? {
+            return Err(EditError::Rejected);
+        }
+    };
+
+    if !print_diff(&stored, &edited) {
+        println!("no changes");
+        return Ok(());
+    }
+    if !confirm("apply changes? [y/N] ")? {
+        println!("no environment variables were changed");
+        return Ok(());
+    }
+
+    // the store isn't locked while the editor is open, changes made meanwhile would be overwritten
+    let _lock = WriteManager::lock()?;
+    if WriteManager::is_outdated()? {
+        return Err(EditError::Outdated);
+    }
+    WriteManager::clear()?;
+    edited.iter().try_for_each(EnvironmentVariable::append)?;
+    Ok(WriteManager::finalize()?)
+}
+
+/// Parses the edited lines, unchanged lines keep their stored variable. Nothing is returned if any
+/// line is rejected.
+fn parse(content: &str, stored: &[EnvironmentVariable], rendered: &[String]) -> Option<Vec<EnvironmentVariable>> {
+    let mut rejected = false;
+    let mut defined = HashMap::new();
+
+    let edited = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .filter_map(|(index, line)| {
+            let var = match rendered.iter().position(|rendered| rendered == line) {
+                Some(position) => stored[position].clone(),
+                None => {
+                    let var = EnvironmentVariable::from_friendly(line)
+                        .inspect_err(|error| {
+                            print_error(&format!("line {}: {error}", index + 1));
+                            rejected = true;
+                        })
+                        .ok()?;
+                    // the metadata isn't shown for editing, so it is kept from the stored variant
+                    let previous = stored
+                        .iter()
+                        .find(|stored| stored.name() == var.name() && stored.conditions() == var.conditions());
+                    var.with_metadata(None, &[], previous)
+                },
+            };
+
+            let identity = (var.name().to_string(), var.conditions().to_vec());
+            if let Some(first) = defined.insert(identity, index) {
+                print_error(&format!(
+                    "line {}: '{}' is already defined on line {}", index + 1, var.name(), first + 1
+                ));
+                rejected = true;
+            }
+            Some(var)
+        })
+        .collect::<Vec<_>>();
+
+    (!rejected).then_some(edited)
+}
+
+/// Prints the changed variables, returns whether there are any.
+fn print_diff(stored: &[EnvironmentVariable], edited: &[EnvironmentVariable]) -> bool {
+    let identity = |var: &EnvironmentVariable| (var.name().to_string(), var.conditions().to_vec());
+    let index = |vars: &[EnvironmentVariable]| -> HashMap<(String, Vec<Condition>), String> {
+        vars.iter()
+            .map(|var| (identity(var), var.encode()))
+            .collect()
+    };
+    let (before, after) = (index(stored), index(edited));
+
+    let removed = stored
+        .iter()
+        .filter(|var| !after.contains_key(&identity(var)))
+        .map(|var| format!("- {}", var.friendly()).red());
+    let changed = edited
+        .iter()
+        .filter(|var| before.get(&identity(var)) != Some(&var.encode()))
+        .flat_map(|var| {
+            let stored = stored.iter().find(|stored| identity(stored) == identity(var));
+            stored
+                .map(|stored| format!("- {}", stored.friendly()).red())
+                .into_iter()
+                .chain([format!("+ {}", var.friendly()).green()])
+        });
+
+    let mut any = false;
+    removed.chain(changed)
+        .for_each(|line| {
+            println!("{line}");
+            any = true;
+        });
+    any
+}
+
+fn run_editor(path: &PathBuf) -> Result<(), EditError> {
+    let editor = env::var_os("VISUAL")
+        .filter(|editor| !editor.is_empty())
+        .or_else(|| env::var_os("EDITOR"))
+        .filter(|editor| !editor.is_empty())
+        .unwrap_or_else(|| OsString::from("vi"));
+
+    // the editor may be given with arguments, e.g. EDITOR='code --wait'
+    let mut script = editor.clone();
+    script.push(" \"$1\"");
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(script)
+        .arg("sh")
+        .arg(path)
+        .status()?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(EditError::Editor(editor.to_string_lossy().to_string()))
+    }
+}
+
+/// File only readable by the user, removed once dropped.
+struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    fn create() -> io::Result<Self> {
+        let path = env::temp_dir().join(format!("gexport-{}.env", std::process::id()));
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)?;
+        Ok(Self { path })
+    }
+
+    fn open(&self) -> io::Result<fs::File> {
+        OpenOptions::new().write(true).open(&self.path)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum EditError {
+    Io(ErrorKind),
+    Writer(WriterError),
+    Parser(ParserError),
+    Editor(String),
+    Rejected,
+    Outdated,
+}
+
+impl From<io::Error> for EditError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error.kind())
+    }
+}
+
+impl From<WriterError> for EditError {
+    fn from(error: WriterError) -> Self {
+        Self::Writer(error)
+    }
+}
+
+impl From<ParserError> for EditError {
+    fn from(error: ParserError) -> Self {
+        Self::Parser(error)
+    }
+}
+
+impl Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "cannot edit, {error:?}"),
+            Self::Writer(error) => write!(f, "{error}"),
+            Self::Parser(error) => write!(f, "cannot edit, {error}"),
+            Self::Editor(editor) => write!(f, "cannot edit, '{editor}' failed"),
+            Self::Rejected => write!(f, "edit rejected, no environment variables were changed"),
+            Self::Outdated => {
+                write!(f, "the stored variables were changed while editing, no environment variables were changed")
+            },
+        }
+    }
+}
+
+impl Error for EditError {}
@@ -1,17 +1,46 @@
+mod attribute;
+mod condition;
 mod encode;
+mod friendly;
+mod layer;
+mod metadata;
 mod parse;
+mod producer;
+mod render;
+mod template;
+mod tombstone;
 pub(crate) mod writer;
 
-use crate::utils::print_error;
+pub(crate) use attribute::Attribute;
+pub(crate) use condition::Condition;
+pub(crate) use encode::EncodeAction;
+pub(crate) use layer::Layer;
+pub(crate) use tombstone::is_tombstone;
+pub(crate) use parse::ParserError;
+pub(crate) use producer::{recompute, Producer};
+pub(crate) use render::{render, render_tombstones};
+
+use crate::utils::{glob_match, print_error, print_warning};
+use metadata::Metadata;
+use producer::Computed;
 use std::ffi::OsString;
 use std::os::unix::ffi::OsStrExt;
 use itertools::Itertools;
 use regex::Regex;
 
-#[derive(Debug)]
+/// Prefix of the variables gexport manages itself, which can't be imported.
+const RESERVED_PREFIX: &str = "GEXPORT_";
+
+#[derive(Clone, Debug)]
 pub(crate) struct EnvironmentVariable {
     name: String,
     value: Vec<u8>,
+    action: EncodeAction,
+    attribute: Option<Attribute>,
+    conditions: Vec<Condition>,
+    template: bool,
+    computed: Option<Computed>,
+    metadata: Metadata,
 }
 
 impl EnvironmentVariable {
@@ -28,7 +57,41 @@ impl EnvironmentVariable {
             .collect::<Vec<_>>()
     }
 
-    pub(crate) fn from_names(names: &Vec<OsString>) -> Vec<Self> {
+    pub(crate) fn with_action(self, action: EncodeAction) -> Self {
+        Self { action, ..self }
+    }
+
+    pub(crate) fn with_conditions(self, conditions: &[Condition]) -> Self {
+        Self { conditions: conditions.to_vec(), ..self }
+    }
+
+    /// Marks the value as template, references to other variables are resolved in each shell.
+    pub(crate) fn with_template(self, template: bool) -> Self {
+        Self { template, ..self }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn conditions(&self) -> &[Condition] {
+        &self.conditions
+    }
+
+    pub(crate) fn action(&self) -> EncodeAction {
+        self.action
+    }
+
+    pub(crate) fn is_template(&self) -> bool {
+        self.template
+    }
+
+    /// Whether all conditions hold in the shell gexport was called from.
+    pub(crate) fn applies(&self) -> bool {
+        self.conditions.iter().all(Condition::applies)
+    }
+
+    pub(crate) fn from_names(names: &[OsString]) -> Vec<Self> {
         names.iter()
             .unique()
             .map(|str| str.as_bytes().to_vec())
@@ -41,27 +104,93 @@ impl EnvironmentVariable {
             .collect::<Vec<_>>()
     }
 
-    const LINE_PATTERN: &'static str = r"^(?:declare -g(?: \+)?x|unset) ";
-
-    fn from_lines(lines: &Vec<String>) -> Vec<Self> {
-        let capture_name = Regex::new(
-            &format!("{}(?<name>[^=]+)(?:[ =]|$)", Self::LINE_PATTERN)
-        ).unwrap();
+    /// Replaces glob patterns such as `AWS_*`, or all selectors if `regex` is set, by the matching
+    /// names in `names`. Other selectors are kept as they are.
+    pub(crate) fn expand(selectors: &[OsString], regex: bool, names: &[String]) -> Result<Vec<OsString>, ParserError> {
+        let mut expanded = Vec::new();
+        for selector in selectors {
+            let pattern = selector.to_string_lossy();
+            if regex {
+                let regex = Regex::new(&pattern)
+                    .map_err(|_| ParserError::InvalidPattern(pattern.to_string()))?;
+                expanded.extend(names.iter().filter(|name| regex.is_match(name)).map(OsString::from));
+            } else if pattern.contains(['*', '?']) {
+                expanded.extend(names.iter()
+                    .filter(|name| glob_match(pattern.as_bytes(), name.as_bytes()))
+                    .map(OsString::from));
+            } else {
+                expanded.push(selector.clone());
+            }
+        }
+        Ok(expanded.into_iter().unique().collect())
+    }
 
-        lines.iter()
-            .filter_map(|line| {
-                capture_name.captures(line)
-            })
-            .map(|capture| {
-                capture["name"].as_bytes().to_vec()
+    /// Parses every line of an import, nothing is returned if any line is rejected. Variables reserved
+    /// for gexport are rejected, and producers are dropped so an import never runs commands, only
+    /// their last output is kept.
+    pub(crate) fn from_import(import: &[u8]) -> Option<Vec<Self>> {
+        let mut rejected = false;
+        let vars = String::from_utf8_lossy(import)
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .filter_map(|(index, line)| {
+                Self::from_line(line)
+                    .and_then(|var| if var.name.starts_with(RESERVED_PREFIX) {
+                        Err(ParserError::Reserved(var.name))
+                    } else {
+                        Ok(var)
+                    })
+                    .inspect_err(|error| {
+                        print_error(&format!("line {}: {error}", index + 1));
+                        rejected = true;
+                    })
+                    .ok()
             })
-            .unique()
-            .map(EnvironmentVariable::new)
-            .filter_map(|var| {
-                var.inspect_err(|error| {
-                    print_error(error);
-                }).ok()
+            .map(|var| match var.computed {
+                Some(_) => {
+                    print_warning(&format!("'{}' is imported without its producer, use --command or --builtin \
+                        to recompute it", var.name));
+                    Self { computed: None, ..var }
+                },
+                None => var,
             })
-            .collect::<Vec<_>>()
+            .collect::<Vec<_>>();
+
+        (!rejected).then_some(vars)
     }
-}
\ No newline at end of file
+}
+
+/// Pseudo random values for tests, reproducible by seed and covering control characters as well as
+/// invalid UTF-8.
+#[cfg(test)]
+pub(crate) fn random_bytes(seed: u64, len: usize) -> Vec<u8> {
+    let mut state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 24) as u8
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imports_computed_variables_without_producer() {
+        let vars = EnvironmentVariable::from_import(b"declare -gx A='x' # command='touch /tmp/pwned' updated='1'\n")
+            .unwrap();
+        assert!(vars[0].computed.is_none());
+        assert_eq!(vars[0].encode(), "declare -gx A='x'");
+    }
+
+    #[test]
+    fn rejects_reserved_variables() {
+        assert!(EnvironmentVariable::from_import(b"declare -g +x GEXPORT_REFRESH_AT='0'\n").is_none());
+        assert!(EnvironmentVariable::from_import(b"declare -gx A='1'\nunset GEXPORT_PRIOR_A\n").is_none());
+    }
+}
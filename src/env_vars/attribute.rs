@@ -0,0 +1,97 @@
+use super::EnvironmentVariable;
+use std::error::Error;
+use std::fmt;
+use std::fmt::Display;
+use std::str;
+
+/// Type attribute of a variable, the shells convert values assigned to it accordingly.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum Attribute {
+    Integer,
+    Lower,
+    Upper,
+}
+
+impl EnvironmentVariable {
+    /// Sets the attribute and converts the value the same way the shells do on assignment, so the
+    /// stored value is the one the shells end up with.
+    pub(crate) fn with_attribute(self, attribute: Option<Attribute>) -> Result<Self, AttributeError> {
+        let Some(attribute) = attribute else {
+            return Ok(self);
+        };
+
+        let value = str::from_utf8(&self.value)
+            .map_err(|_| AttributeError::InvalidUtf8(self.name.clone()))?;
+        let value = match attribute {
+            Attribute::Integer => {
+                value.trim()
+                    .parse::<i64>()
+                    .map_err(|_| AttributeError::NotAnInteger(value.to_string()))?
+                    .to_string()
+            },
+            Attribute::Lower => value.to_lowercase(),
+            Attribute::Upper => value.to_uppercase(),
+        };
+
+        Ok(Self {
+            value: value.into_bytes(),
+            attribute: Some(attribute),
+            ..self
+        })
+    }
+}
+
+impl Attribute {
+    /// Option of `declare` setting the attribute.
+    pub(super) fn option(self) -> char {
+        match self {
+            Self::Integer => 'i',
+            Self::Lower => 'l',
+            Self::Upper => 'u',
+        }
+    }
+
+    pub(super) fn from_option(option: char) -> Option<Self> {
+        match option {
+            'i' => Some(Self::Integer),
+            'l' => Some(Self::Lower),
+            'u' => Some(Self::Upper),
+            _ => None,
+        }
+    }
+
+    /// Command line flag setting the attribute.
+    pub(super) fn flag(self) -> &'static str {
+        match self {
+            Self::Integer => "--integer",
+            Self::Lower => "--lower",
+            Self::Upper => "--upper",
+        }
+    }
+
+    pub(super) fn from_flag(flag: &str) -> Option<Self> {
+        match flag {
+            "--integer" => Some(Self::Integer),
+            "--lower" => Some(Self::Lower),
+            "--upper" => Some(Self::Upper),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum AttributeError {
+    NotAnInteger(String),
+    InvalidUtf8(String),
+}
+
+impl Display for AttributeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAnInteger(value) => write!(f, "'{value}' is not an integer"),
+            Self::InvalidUtf8(name) => write!(f, "value of '{name}' is not valid UTF-8"),
+        }
+    }
+}
+
+impl Error for AttributeError {}
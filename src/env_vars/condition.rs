@@ -0,0 +1,115 @@
+use crate::utils::{current_shell, glob_match, hostname};
+use super::parse::ParserError;
+use std::env;
+use std::fmt::Display;
+use std::fmt;
+use std::str::FromStr;
+use regex::Regex;
+
+/// Restricts a variable to shells where `key` matches the glob `pattern`.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct Condition {
+    key: ConditionKey,
+    pattern: String,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) enum ConditionKey {
+    Host,
+    Shell,
+    Os,
+}
+
+impl ConditionKey {
+    const ALL: [Self; 3] = [Self::Host, Self::Shell, Self::Os];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Host => "host",
+            Self::Shell => "shell",
+            Self::Os => "os",
+        }
+    }
+
+    /// Shell expression the pattern is matched against, valid in both Bash and Zsh.
+    fn subject(&self) -> &'static str {
+        match self {
+            Self::Host => "${HOSTNAME:-$HOST}",
+            Self::Shell => "${BASH_VERSION:+bash}${ZSH_VERSION:+zsh}",
+            Self::Os => "${OSTYPE%%[0-9.-]*}",
+        }
+    }
+
+    /// Value of the subject in the shell gexport was called from.
+    fn current(&self) -> String {
+        match self {
+            Self::Host => hostname(),
+            Self::Shell => current_shell(),
+            Self::Os => match env::consts::OS {
+                "macos" => "darwin",
+                os => os,
+            }.to_string(),
+        }
+    }
+}
+
+impl Condition {
+    pub(crate) fn new(key: &str, pattern: &str) -> Result<Self, ParserError> {
+        let key = ConditionKey::ALL
+            .into_iter()
+            .find(|candidate| candidate.name() == key)
+            .ok_or_else(|| ParserError::InvalidCondition(format!("{key}={pattern}")))?;
+
+        // the pattern is placed unquoted into the guard, only allow plain glob patterns
+        let valid_pattern = Regex::new(r"^[a-zA-Z0-9_.*?-]+$").unwrap();
+        if !valid_pattern.is_match(pattern) {
+            return Err(ParserError::InvalidCondition(format!("{}={pattern}", key.name())));
+        }
+
+        Ok(Self {
+            key,
+            pattern: pattern.to_string(),
+        })
+    }
+
+    /// Encodes the condition as guard for a declaration, e.g. `[[ ${HOSTNAME:-$HOST} == build-* ]] && `.
+    pub(crate) fn encode(&self) -> String {
+        format!("[[ {} == {} ]] && ", self.key.subject(), self.pattern)
+    }
+
+    /// Whether the condition holds in the shell gexport was called from.
+    pub(crate) fn applies(&self) -> bool {
+        glob_match(self.pattern.as_bytes(), self.key.current().as_bytes())
+    }
+
+    /// Parses a guard as produced by `encode`, returning the condition and the remaining line.
+    pub(crate) fn from_guard(line: &str) -> Option<(Self, &str)> {
+        let guard = line.strip_prefix("[[ ")?;
+        let (test, rest) = guard.split_once(" ]] && ")?;
+        let (subject, pattern) = test.split_once(" == ")?;
+        let key = ConditionKey::ALL
+            .into_iter()
+            .find(|key| key.subject() == subject)?;
+
+        Self::new(key.name(), pattern)
+            .ok()
+            .map(|condition| (condition, rest))
+    }
+}
+
+impl FromStr for Condition {
+    type Err = ParserError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (key, pattern) = input
+            .split_once('=')
+            .ok_or_else(|| ParserError::InvalidCondition(input.to_string()))?;
+        Self::new(key, pattern)
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.key.name(), self.pattern)
+    }
+}
@@ -1,30 +1,95 @@
 use super::EnvironmentVariable;
+use super::condition::Condition;
+use super::template;
+use super::template::Segment;
+use super::producer::Computed;
 use std::ops::Index;
 
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub(crate) enum EncodeAction {
     Default,
     Unexport,
     Unset,
+    Readonly,
 }
 
+/// Silences the error of redeclaring a read-only variable each time the shell sources the store.
+pub(super) const READONLY_REDIRECT: &str = " 2>/dev/null";
+
 impl EnvironmentVariable {
-    pub(crate) fn encode(&self, action: EncodeAction) -> String {
-        match action {
-            EncodeAction::Unset => format!("unset {0}", self.name),
+    pub(crate) fn encode(&self) -> String {
+        let guards = self.conditions
+            .iter()
+            .map(Condition::encode)
+            .collect::<String>();
+
+        let declaration = match self.action {
+            EncodeAction::Unset => {
+                format!("unset {0}{1}", self.name, encode_annotations(&self.metadata.annotations()))
+            },
             _ => {
-                let export = match action {
-                    EncodeAction::Default => "x",
-                    EncodeAction::Unexport => " +x",
+                let (export, redirect) = match self.action {
+                    EncodeAction::Default => ("x", ""),
+                    EncodeAction::Unexport => (" +x", ""),
+                    EncodeAction::Readonly => ("rx", READONLY_REDIRECT),
                     _ => unreachable!(),
                 };
-                let value = ansi_c_encode(&self.value);
-                let ansi_prefix = if value.contains('\\') { "$" } else { "" };
-                format!("declare -g{export} {}={ansi_prefix}'{value}'", self.name)
+                let value = if self.template {
+                    encode_template(&self.value)
+                } else {
+                    quote(&self.value)
+                };
+                let mut annotations = self.computed
+                    .as_ref()
+                    .map(Computed::annotations)
+                    .unwrap_or_default();
+                annotations.extend(self.metadata.annotations());
+                let attribute = self.attribute
+                    .map(|attribute| attribute.option().to_string())
+                    .unwrap_or_default();
+                format!("declare -g{attribute}{export} {}={value}{redirect}{}", self.name, encode_annotations(&annotations))
             }
-        }
+        };
+
+        format!("{guards}{declaration}")
     }
 }
 
+fn quote(input: &[u8]) -> String {
+    let value = ansi_c_encode(input);
+    let ansi_prefix = if value.contains('\\') { "$" } else { "" };
+    format!("{ansi_prefix}'{value}'")
+}
+
+/// Encodes additional information about a declaration as trailing comment, ignored by the shell.
+fn encode_annotations(annotations: &[(&str, Vec<u8>)]) -> String {
+    if annotations.is_empty() {
+        return String::new();
+    }
+
+    let annotations = annotations
+        .iter()
+        .map(|(key, value)| format!("{key}={}", quote(value)))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(" # {annotations}")
+}
+
+/// Encodes references as double quoted expansions, so they are resolved whenever the line is sourced.
+fn encode_template(template: &[u8]) -> String {
+    let encoded = template::parse(template)
+        .iter()
+        .map(|segment| {
+            match segment {
+                Segment::Literal(literal) => quote(literal),
+                Segment::Reference(name) => format!("\"${{{name}}}\""),
+            }
+        })
+        .collect::<String>();
+
+    if encoded.is_empty() { quote(&[]) } else { encoded }
+}
+
 fn ansi_c_encode(input: &[u8]) -> String {
     let mut encoded = String::with_capacity(input.len() * 2);
     input.iter()
@@ -59,7 +124,7 @@ impl EncodedByte {
             0x20..=0x26 | 0x28..=0x5B | 0x5D..=0x7E => [byte, 0, 0, 0],
             0x0A => ['\\' as u8, 'n' as u8, 0, 0],
             0x0D => ['\\' as u8, 'r' as u8, 0, 0],
-            0x09 => ['\\' as u8, 'h' as u8, 0, 0],
+            0x09 => ['\\' as u8, 't' as u8, 0, 0],
             0x0B => ['\\' as u8, 'v' as u8, 0, 0],
             0x0C => ['\\' as u8, 'f' as u8, 0, 0],
             0x08 => ['\\' as u8, 'b' as u8, 0, 0],
@@ -123,3 +188,22 @@ impl<'a> Iterator for EncodedByteIter<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn encodes_tabs_as_ansi_c_escape() {
+        let encoded = ansi_c_encode(b"a\tb");
+        assert_eq!(encoded, "a\\tb");
+
+        // \h is no escape, bash kept it literally
+        let output = Command::new("bash")
+            .args(["--norc", "-c", &format!("printf %s $'{encoded}'")])
+            .output()
+            .unwrap();
+        assert_eq!(output.stdout, b"a\tb");
+    }
+}
@@ -0,0 +1,282 @@
+use crate::args::parse_duration;
+use super::EnvironmentVariable;
+use super::attribute::{Attribute, AttributeError};
+use super::encode::EncodeAction;
+use super::parse::ParserError;
+use super::producer::{Producer, ProducerError};
+use std::error::Error;
+use std::fmt;
+use std::fmt::Display;
+
+impl EnvironmentVariable {
+    /// Renders the variable for editing as `NAME=value` with the decoded value, preceded by the
+    /// command line flags needed to recreate it.
+    pub(crate) fn friendly(&self) -> String {
+        let mut flags = self.conditions
+            .iter()
+            .map(|condition| format!("--when {condition} "))
+            .collect::<String>();
+
+        match self.action {
+            EncodeAction::Default => {},
+            EncodeAction::Unexport => flags.push_str("-n "),
+            EncodeAction::Readonly => flags.push_str("-r "),
+            EncodeAction::Unset => return format!("{flags}-u {}", self.name),
+        }
+        if let Some(attribute) = self.attribute {
+            flags.push_str(&format!("{} ", attribute.flag()));
+        }
+        if self.template {
+            flags.push_str("-t ");
+        }
+
+        let value = match &self.computed {
+            Some(computed) => {
+                flags.push_str(match computed.producer {
+                    Producer::Command(_) => "-c ",
+                    Producer::Builtin(_) => "--builtin ",
+                });
+                if let Some(refresh) = computed.refresh {
+                    flags.push_str(&format!("--refresh {} ", format_duration(refresh)));
+                }
+                computed.producer.source()
+            },
+            None => self.value.clone(),
+        };
+
+        format!("{flags}{}={}", self.name, escape(&value))
+    }
+
+    /// Inverse of `friendly`, the producers of computed variables are run again.
+    pub(crate) fn from_friendly(line: &str) -> Result<Self, FriendlyError> {
+        let unrecognized = || ParserError::UnrecognizedLine(line.to_string());
+
+        let mut conditions = Vec::new();
+        let mut action = EncodeAction::Default;
+        let mut attribute = None;
+        let mut template = false;
+        let mut producer = None;
+        let mut refresh = None;
+
+        let mut rest = line;
+        loop {
+            let (flag, tail) = rest.split_once(' ').unwrap_or((rest, ""));
+            match flag {
+                "-n" => action = EncodeAction::Unexport,
+                "-u" => action = EncodeAction::Unset,
+                "-r" => action = EncodeAction::Readonly,
+                "--integer" | "--lower" | "--upper" => attribute = Attribute::from_flag(flag),
+                "-t" => template = true,
+                "-c" => producer = Some(false),
+                "--builtin" => producer = Some(true),
+                "--when" | "--refresh" => {
+                    let (argument, tail) = tail.split_once(' ').ok_or_else(unrecognized)?;
+                    if flag == "--when" {
+                        conditions.push(argument.parse()?);
+                    } else {
+                        refresh = Some(parse_duration(argument).map_err(|_| unrecognized())?);
+                    }
+                    rest = tail;
+                    continue;
+                },
+                _ => break,
+            }
+            rest = tail;
+        }
+
+        if action == EncodeAction::Unset {
+            if attribute.is_some() || template || producer.is_some() || refresh.is_some() || rest.contains('=') {
+                return Err(unrecognized().into());
+            }
+            let mut this = Self::new(rest.as_bytes().to_vec())?;
+            this.action = action;
+            this.conditions = conditions;
+            return Ok(this);
+        }
+
+        let (name, value) = rest.split_once('=').ok_or_else(unrecognized)?;
+        let mut this = Self::new(name.as_bytes().to_vec())?;
+        this.value = unescape(value.as_bytes());
+        this.action = action;
+        this.conditions = conditions;
+
+        match producer {
+            Some(builtin) if !template && attribute.is_none() => Ok(this.with_producer(builtin, refresh)?),
+            None if refresh.is_none() && !(template && attribute.is_some()) => {
+                Ok(this.with_template(template).with_attribute(attribute)?)
+            },
+            _ => Err(unrecognized().into()),
+        }
+    }
+}
+
+/// Escapes the value so it fits on a single line, a backslash is only escaped where the escaped
+/// text following it would otherwise turn it into an escape sequence.
+fn escape(value: &[u8]) -> String {
+    let hex = |bytes: &[u8]| bytes.iter().map(|byte| format!("\\x{byte:02x}")).collect::<String>();
+
+    // escaped chars, None for a backslash, which depends on what follows
+    let mut pieces = Vec::with_capacity(value.len());
+    for chunk in value.utf8_chunks() {
+        pieces.extend(chunk.valid().chars().map(|char| match char {
+            '\n' => Some("\\n".to_string()),
+            '\t' => Some("\\t".to_string()),
+            '\\' => None,
+            char if char.is_control() => Some(hex(char.encode_utf8(&mut [0; 4]).as_bytes())),
+            char => Some(char.to_string()),
+        }));
+        if !chunk.invalid().is_empty() {
+            pieces.push(Some(hex(chunk.invalid())));
+        }
+    }
+
+    let mut escaped = String::with_capacity(value.len());
+    for (index, piece) in pieces.iter().enumerate() {
+        match piece {
+            Some(piece) => escaped.push_str(piece),
+            None => {
+                let next = pieces.get(index + 1).map(|next| next.as_deref().unwrap_or("\\"));
+                if next.is_some_and(|next| next.starts_with(['n', 't', 'x', '\\'])) {
+                    escaped.push('\\');
+                }
+                escaped.push('\\');
+            },
+        }
+    }
+    escaped
+}
+
+/// Inverse of `escape`, backslashes not starting an escape sequence are kept literally.
+fn unescape(value: &[u8]) -> Vec<u8> {
+    let mut unescaped = Vec::with_capacity(value.len());
+    let mut index = 0;
+
+    while index < value.len() {
+        let byte = match (value[index], value.get(index + 1)) {
+            (b'\\', Some(b'n')) => Some(b'\n'),
+            (b'\\', Some(b't')) => Some(b'\t'),
+            (b'\\', Some(b'\\')) => Some(b'\\'),
+            _ => None,
+        };
+        if let Some(byte) = byte {
+            unescaped.push(byte);
+            index += 2;
+            continue;
+        }
+
+        let hex = value
+            .get(index + 2..index + 4)
+            .filter(|_| value[index..].starts_with(b"\\x"))
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+        if let Some(byte) = hex {
+            unescaped.push(byte);
+            index += 4;
+        } else {
+            unescaped.push(value[index]);
+            index += 1;
+        }
+    }
+    unescaped
+}
+
+fn format_duration(seconds: u64) -> String {
+    [("d", 24 * 60 * 60), ("h", 60 * 60), ("m", 60)]
+        .into_iter()
+        .find(|(_, factor)| seconds != 0 && seconds.is_multiple_of(*factor))
+        .map(|(unit, factor)| format!("{}{unit}", seconds / factor))
+        .unwrap_or_else(|| format!("{seconds}s"))
+}
+
+#[derive(Debug)]
+pub(crate) enum FriendlyError {
+    Parser(ParserError),
+    Producer(ProducerError),
+    Attribute(AttributeError),
+}
+
+impl From<ParserError> for FriendlyError {
+    fn from(error: ParserError) -> Self {
+        Self::Parser(error)
+    }
+}
+
+impl From<ProducerError> for FriendlyError {
+    fn from(error: ProducerError) -> Self {
+        Self::Producer(error)
+    }
+}
+
+impl From<AttributeError> for FriendlyError {
+    fn from(error: AttributeError) -> Self {
+        Self::Attribute(error)
+    }
+}
+
+impl Display for FriendlyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parser(error) => write!(f, "{error}"),
+            Self::Producer(error) => write!(f, "{error}"),
+            Self::Attribute(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl Error for FriendlyError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env_vars::random_bytes;
+
+    fn assert_round_trip(value: &[u8]) {
+        let escaped = escape(value);
+        assert!(!escaped.contains(['\n', '\r']), "{escaped}");
+        assert_eq!(unescape(escaped.as_bytes()), value, "{escaped}");
+    }
+
+    #[test]
+    fn escapes_single_line() {
+        assert_eq!(escape(b"a\nb\tc"), "a\\nb\\tc");
+        assert_eq!(escape(b"a\x01b\x7f"), "a\\x01b\\x7f");
+        assert_eq!(escape(b"a\xffb"), "a\\xffb");
+        assert_eq!(escape("é€".as_bytes()), "é€");
+    }
+
+    #[test]
+    fn escapes_backslashes_only_where_needed() {
+        assert_eq!(escape(br"C:\Users\me"), r"C:\Users\me");
+        assert_eq!(escape(br"a\nb"), r"a\\nb");
+        assert_eq!(escape(br"a\\b"), r"a\\\b");
+        assert_eq!(escape(b"a\\\x01b"), r"a\\\x01b");
+        assert_eq!(escape(b"a\\\tb"), r"a\\\tb");
+        assert_eq!(escape(b"a\\\xffb"), r"a\\\xffb");
+        assert_eq!(escape(br"a\"), r"a\");
+    }
+
+    #[test]
+    fn round_trips_values() {
+        [
+            b"".as_slice(), b"\\", b"\\\\", b"a\\", b"a\\\\", b"\\\x01", b"\\\t", b"\\\n", b"\\\xff",
+            b"\\x41", b"\\x4", b"\\n\\t", b"\\\\\\n", b"\x00\x1b[0m", b"\xc3\x28\\", "\\é".as_bytes(),
+        ]
+            .into_iter()
+            .for_each(assert_round_trip);
+
+        (0..=255u8)
+            .flat_map(|first| [[first, b'\\'], [b'\\', first]])
+            .for_each(|value| assert_round_trip(&value));
+        (0..256).for_each(|seed| assert_round_trip(&random_bytes(seed, 32)));
+        // a third backslashes, next to control chars and invalid UTF-8
+        (0..256)
+            .map(|seed| random_bytes(seed, 32).into_iter().map(|byte| if byte % 3 == 0 { b'\\' } else { byte }))
+            .for_each(|value| assert_round_trip(&value.collect::<Vec<_>>()));
+    }
+
+    #[test]
+    fn keeps_unknown_escapes() {
+        assert_eq!(unescape(br"a\qb\x4"), br"a\qb\x4");
+        assert_eq!(unescape(br"\x4G"), br"\x4G");
+    }
+}
@@ -0,0 +1,105 @@
+use crate::args::Scope;
+use super::{EncodeAction, EnvironmentVariable};
+use super::writer::{WriteManager, WriterError};
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::PathBuf;
+use fs2::FileExt;
+use clap::ValueEnum;
+use itertools::Itertools;
+
+/// One of the env-var files sourced by the calling shell.
+pub(crate) struct Layer {
+    pub(crate) name: String,
+    pub(crate) location: PathBuf,
+    pub(crate) lines: Vec<String>,
+    /// Names of the variables locked by the system store, which the shells refuse to override.
+    locked: Vec<String>,
+}
+
+impl Layer {
+    /// The layers of the calling shell in order of increasing precedence, a variable in a later
+    /// layer overrides the same variable in the earlier ones.
+    pub(crate) fn all() -> Result<Vec<Self>, WriterError> {
+        let system = Self::system()?;
+        let locked = system.locked_names();
+
+        let mut layers = vec![system, Self::read("global", WriteManager::global_location())?];
+        for scope in Scope::value_variants() {
+            if let Some(location) = scope.location() {
+                let name = scope.to_possible_value().unwrap().get_name().to_string();
+                layers.push(Self::read(&name, location)?);
+            }
+        }
+        layers.iter_mut()
+            .skip(1)
+            .for_each(|layer| layer.locked = locked.clone());
+        Ok(layers)
+    }
+
+    /// The layer set up by the administrator for all users, see --system.
+    pub(crate) fn system() -> Result<Self, WriterError> {
+        Self::read("system", WriteManager::system_location())
+    }
+
+    fn read(name: &str, location: PathBuf) -> Result<Self, WriterError> {
+        let mut data = Vec::new();
+        match File::open(&location) {
+            Ok(mut file) => {
+                FileExt::lock_shared(&file)?;
+                file.read_to_end(&mut data)?;
+                FileExt::unlock(&file)?;
+            },
+            Err(error) if error.kind() == ErrorKind::NotFound => {},
+            Err(error) => return Err(error.into()),
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            location,
+            lines: String::from_utf8_lossy(&data)
+                .lines()
+                .map(str::to_string)
+                .collect(),
+            locked: Vec::new(),
+        })
+    }
+
+    /// The variables of the layer which apply to the calling shell, in sourcing order. Those
+    /// overriding a variable locked by the system store are left out.
+    pub(crate) fn vars(&self) -> impl Iterator<Item = EnvironmentVariable> {
+        self.lines
+            .iter()
+            .filter_map(|line| EnvironmentVariable::from_line(line).ok())
+            .filter(EnvironmentVariable::applies)
+            .filter(|var| !self.locked.iter().any(|name| name == var.name()))
+    }
+
+    /// Names of the read-only variables of the layer which apply to the calling shell.
+    pub(crate) fn locked_names(&self) -> Vec<String> {
+        self.vars()
+            .filter(|var| var.action() == EncodeAction::Readonly)
+            .map(|var| var.name)
+            .unique()
+            .collect()
+    }
+
+    /// Names of the variables of the layer which override one locked by the system store.
+    pub(crate) fn overriding_names(&self) -> Vec<String> {
+        self.names()
+            .into_iter()
+            .filter(|name| self.locked.contains(name))
+            .collect()
+    }
+
+    /// Names of the variables in the layer in order of appearance, excluding gexport's own.
+    pub(crate) fn names(&self) -> Vec<String> {
+        self.lines
+            .iter()
+            .filter_map(|line| EnvironmentVariable::from_line(line).ok())
+            .filter(|var| !var.is_internal())
+            .map(|var| var.name)
+            .unique()
+            .collect()
+    }
+}
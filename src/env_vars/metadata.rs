@@ -0,0 +1,79 @@
+use crate::utils::owner;
+use super::EnvironmentVariable;
+
+/// Information about who stored a variable and why, kept alongside the value.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub(crate) struct Metadata {
+    description: Option<String>,
+    tags: Vec<String>,
+    owner: Option<String>,
+}
+
+impl EnvironmentVariable {
+    /// Sets the description and tags, those not given are kept from the `stored` variable. The owner
+    /// becomes the current user.
+    pub(crate) fn with_metadata(self, description: Option<&str>, tags: &[String], stored: Option<&Self>) -> Self {
+        let stored = stored.map(|stored| stored.metadata.clone()).unwrap_or_default();
+        let metadata = Metadata {
+            description: description.map(str::to_string).or(stored.description),
+            tags: if tags.is_empty() { stored.tags } else { tags.to_vec() },
+            owner: Some(owner()),
+        };
+        Self { metadata, ..self }
+    }
+
+    /// Whether the variable is tagged with all of the given tags.
+    pub(crate) fn has_tags(&self, tags: &[String]) -> bool {
+        tags.iter().all(|tag| self.metadata.tags.contains(tag))
+    }
+
+    /// Renders the metadata as indented `key: value` lines, for `--print --long`.
+    pub(crate) fn describe(&self) -> String {
+        let mut lines = Vec::new();
+        if let Some(description) = &self.metadata.description {
+            lines.push(format!("    description: {description}"));
+        }
+        if !self.metadata.tags.is_empty() {
+            lines.push(format!("    tags: {}", self.metadata.tags.join(", ")));
+        }
+        if let Some(owner) = &self.metadata.owner {
+            lines.push(format!("    owner: {owner}"));
+        }
+        lines.join("\n")
+    }
+}
+
+impl Metadata {
+    pub(crate) const KEYS: [&'static str; 3] = ["description", "tags", "owner"];
+
+    pub(crate) fn annotations(&self) -> Vec<(&'static str, Vec<u8>)> {
+        let mut annotations = Vec::new();
+        if let Some(description) = &self.description {
+            annotations.push(("description", description.as_bytes().to_vec()));
+        }
+        if !self.tags.is_empty() {
+            annotations.push(("tags", self.tags.join(",").into_bytes()));
+        }
+        if let Some(owner) = &self.owner {
+            annotations.push(("owner", owner.as_bytes().to_vec()));
+        }
+        annotations
+    }
+
+    /// Inverse of `annotations`, fails on unknown keys.
+    pub(crate) fn from_annotations(annotations: &[(String, Vec<u8>)]) -> Option<Self> {
+        let mut metadata = Self::default();
+
+        for (key, value) in annotations {
+            let value = String::from_utf8(value.clone()).ok()?;
+            match key.as_str() {
+                "description" => metadata.description = Some(value),
+                "tags" => metadata.tags = value.split(',').map(str::to_string).collect(),
+                "owner" => metadata.owner = Some(value),
+                _ => return None,
+            }
+        }
+
+        Some(metadata)
+    }
+}
@@ -1,9 +1,18 @@
 use super::EnvironmentVariable;
+use super::encode::{EncodeAction, READONLY_REDIRECT};
+use super::attribute::Attribute;
+use super::condition::Condition;
+use super::template;
+use super::template::Segment;
+use super::metadata::Metadata;
+use super::producer::Computed;
 use std::ffi::{OsStr, OsString};
 use std::error::Error;
 use std::fmt::Display;
 use std::fmt;
 use std::env;
+use std::slice::Iter;
+use std::str;
 use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use regex::Regex;
 
@@ -24,6 +33,12 @@ impl EnvironmentVariable {
             Ok(Self {
                 name,
                 value: Vec::default(),
+                action: EncodeAction::Default,
+                conditions: Vec::default(),
+                template: false,
+                computed: None,
+                attribute: None,
+                metadata: Metadata::default(),
             })
         }
     }
@@ -56,23 +71,352 @@ impl EnvironmentVariable {
         Ok(this)
     }
 
+    /// Parses a single line as produced by `encode`, anything else is rejected.
+    pub(crate) fn from_line(line: &str) -> Result<Self, ParserError> {
+        let unrecognized = || ParserError::UnrecognizedLine(line.to_string());
+
+        let mut conditions = Vec::new();
+        let mut rest = line;
+        while rest.starts_with("[[ ") {
+            let (condition, declaration) = Condition::from_guard(rest).ok_or_else(unrecognized)?;
+            conditions.push(condition);
+            rest = declaration;
+        }
+
+        if let Some(declaration) = rest.strip_prefix("unset ") {
+            let (name, annotations) = declaration.split_once(' ').unwrap_or((declaration, ""));
+            let mut this = Self::new(name.as_bytes().to_vec())?;
+            if !annotations.is_empty() {
+                let annotations = annotations
+                    .strip_prefix("# ")
+                    .and_then(|annotations| decode_annotations(annotations.as_bytes()))
+                    .ok_or_else(unrecognized)?;
+                this.metadata = Metadata::from_annotations(&annotations).ok_or_else(unrecognized)?;
+            }
+            this.action = EncodeAction::Unset;
+            this.conditions = conditions;
+            return Ok(this);
+        }
+
+        let options = rest.strip_prefix("declare -g").ok_or_else(unrecognized)?;
+        let attribute = options.chars().next().and_then(Attribute::from_option);
+        let options = if attribute.is_some() { &options[1..] } else { options };
+
+        let (action, declaration) = if let Some(declaration) = options.strip_prefix("x ") {
+            (EncodeAction::Default, declaration)
+        } else if let Some(declaration) = options.strip_prefix(" +x ") {
+            (EncodeAction::Unexport, declaration)
+        } else if let Some(declaration) = options.strip_prefix("rx ") {
+            (EncodeAction::Readonly, declaration)
+        } else {
+            return Err(unrecognized());
+        };
+
+        let (name, value) = declaration
+            .split_once('=')
+            .ok_or_else(unrecognized)?;
+
+        let mut this = Self::new(name.as_bytes().to_vec())?;
+        let (segments, mut rest) = decode_value(value.as_bytes()).ok_or_else(unrecognized)?;
+        if action == EncodeAction::Readonly {
+            rest = rest.strip_prefix(READONLY_REDIRECT.as_bytes()).ok_or_else(unrecognized)?;
+        }
+        if !rest.is_empty() {
+            let annotations = rest
+                .strip_prefix(b" # ")
+                .and_then(decode_annotations)
+                .ok_or_else(unrecognized)?;
+            let (metadata, computed): (Vec<_>, Vec<_>) = annotations
+                .into_iter()
+                .partition(|(key, _)| Metadata::KEYS.contains(&key.as_str()));
+            this.computed = Computed::from_annotations(&computed).ok_or_else(unrecognized)?;
+            this.metadata = Metadata::from_annotations(&metadata).ok_or_else(unrecognized)?;
+        }
+
+        this.template = segments
+            .iter()
+            .any(|segment| matches!(segment, Segment::Reference(_)));
+        this.value = if this.template {
+            template::source(&segments)
+        } else {
+            segments
+                .into_iter()
+                .flat_map(|segment| match segment {
+                    Segment::Literal(literal) => literal,
+                    Segment::Reference(_) => unreachable!(),
+                })
+                .collect()
+        };
+        // bash evaluates the values of integer variables as arithmetic, which can run commands
+        if attribute == Some(Attribute::Integer) {
+            str::from_utf8(&this.value)
+                .ok()
+                .and_then(|value| value.parse::<i64>().ok())
+                .ok_or_else(unrecognized)?;
+        }
+        this.action = action;
+        this.attribute = attribute;
+        this.conditions = conditions;
+
+        Ok(this)
+    }
+
     pub(crate) fn is_identifier_valid(name: &str) -> bool {
         let valid_name = Regex::new("^[a-zA-Z_][a-zA-Z0-9_]*$").unwrap();
         valid_name.is_match(name)
     }
 }
 
+/// Decodes a value consisting of adjacent quoted strings and `"${NAME}"` references, up to the
+/// first unquoted space.
+fn decode_value(mut value: &[u8]) -> Option<(Vec<Segment>, &[u8])> {
+    let mut segments = Vec::new();
+
+    while !value.is_empty() && !value.starts_with(b" ") {
+        let (segment, rest) = decode_segment(value)?;
+        segments.push(segment);
+        value = rest;
+    }
+
+    (!segments.is_empty()).then_some((segments, value))
+}
+
+fn decode_segment(value: &[u8]) -> Option<(Segment, &[u8])> {
+    if let Some(quoted) = value.strip_prefix(b"$'") {
+        let (literal, rest) = ansi_c_decode(quoted)?;
+        Some((Segment::Literal(literal), rest))
+    } else if let Some(quoted) = value.strip_prefix(b"'") {
+        let end = quoted.iter().position(|&byte| byte == b'\'')?;
+        Some((Segment::Literal(quoted[..end].to_vec()), &quoted[end + 1..]))
+    } else if let Some(reference) = value.strip_prefix(b"\"${") {
+        let end = reference.iter().position(|&byte| byte == b'}')?;
+        let name = str::from_utf8(&reference[..end]).ok()?;
+        if !EnvironmentVariable::is_identifier_valid(name) {
+            return None;
+        }
+        Some((Segment::Reference(name.to_string()), reference[end + 1..].strip_prefix(b"\"")?))
+    } else {
+        None
+    }
+}
+
+/// Decodes the `key='value'` pairs trailing a declaration as comment.
+fn decode_annotations(mut input: &[u8]) -> Option<Vec<(String, Vec<u8>)>> {
+    let mut annotations = Vec::new();
+
+    loop {
+        let divider = input.iter().position(|&byte| byte == b'=')?;
+        let key = str::from_utf8(&input[..divider]).ok()?;
+        if key.is_empty() || !key.bytes().all(|byte| byte.is_ascii_lowercase()) {
+            return None;
+        }
+
+        let Some((Segment::Literal(value), rest)) = decode_segment(&input[divider + 1..]) else {
+            return None;
+        };
+        annotations.push((key.to_string(), value));
+
+        if rest.is_empty() {
+            return Some(annotations);
+        }
+        input = rest.strip_prefix(b" ")?;
+    }
+}
+
+/// Decodes the contents of a `$'...'` string up to its closing quote, returning the remaining input.
+fn ansi_c_decode(input: &[u8]) -> Option<(Vec<u8>, &[u8])> {
+    let mut decoded = Vec::with_capacity(input.len());
+    let mut bytes = input.iter();
+
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'\'' => return Some((decoded, bytes.as_slice())),
+            b'\\' => {
+                let escape = *bytes.next()?;
+                let byte = match escape {
+                    b'n' => 0x0A,
+                    b'r' => 0x0D,
+                    b't' => 0x09,
+                    b'v' => 0x0B,
+                    b'f' => 0x0C,
+                    b'b' => 0x08,
+                    b'a' => 0x07,
+                    b'e' | b'E' => 0x1B,
+                    b'\\' | b'\'' | b'"' | b'?' => escape,
+                    b'x' => {
+                        let digits = take_digits(&mut bytes, 2, u8::is_ascii_hexdigit);
+                        u8::from_str_radix(&digits, 16).ok()?
+                    },
+                    b'0'..=b'7' => {
+                        let digits = (escape as char).to_string()
+                            + &take_digits(&mut bytes, 2, |digit| matches!(digit, b'0'..=b'7'));
+                        u16::from_str_radix(&digits, 8).ok()? as u8
+                    },
+                    // unicode and control character escapes are never produced by `encode`
+                    b'u' | b'U' | b'c' => return None,
+                    _ => {
+                        decoded.push(b'\\');
+                        escape
+                    }
+                };
+                decoded.push(byte);
+            },
+            _ => decoded.push(byte),
+        }
+    }
+
+    None
+}
+
+fn take_digits(bytes: &mut Iter<u8>, max: usize, predicate: fn(&u8) -> bool) -> String {
+    let mut digits = String::new();
+    while digits.len() < max && bytes.as_slice().first().is_some_and(predicate) {
+        digits.push(*bytes.next().unwrap() as char);
+    }
+    digits
+}
+
 #[derive(Debug)]
 pub(crate) enum ParserError {
     InvalidIdentifier(String),
+    UnrecognizedLine(String),
+    InvalidCondition(String),
+    InvalidPattern(String),
+    Reserved(String),
 }
 
 impl Display for ParserError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::InvalidIdentifier(name) => write!(f, "'{name}' is not a valid identifier"),
+            Self::UnrecognizedLine(line) => write!(f, "'{line}' is not a recognized declaration"),
+            Self::InvalidCondition(condition) => {
+                write!(f, "'{condition}' is not a valid condition, expected host|shell|os=PATTERN")
+            },
+            Self::InvalidPattern(pattern) => write!(f, "'{pattern}' is not a valid regular expression"),
+            Self::Reserved(name) => write!(f, "'{name}' is reserved for gexport"),
         }
     }
 }
 
-impl Error for ParserError {}
\ No newline at end of file
+impl Error for ParserError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env_vars::random_bytes;
+    use std::process::Command;
+
+    fn var(value: &[u8]) -> EnvironmentVariable {
+        EnvironmentVariable {
+            value: value.to_vec(),
+            ..EnvironmentVariable::new(b"NAME".to_vec()).unwrap()
+        }
+    }
+
+    fn assert_round_trip(var: &EnvironmentVariable) {
+        let line = var.encode();
+        let decoded = EnvironmentVariable::from_line(&line)
+            .unwrap_or_else(|error| panic!("{line}: {error}"));
+        assert_eq!(decoded.value, var.value, "{line}");
+        assert_eq!(decoded.encode(), line);
+    }
+
+    /// Value bash assigns when sourcing the encoded variable.
+    fn sourced(var: &EnvironmentVariable) -> Vec<u8> {
+        let script = format!("{}\nprintf %s \"$NAME\"", var.encode());
+        Command::new("bash")
+            .args(["--norc", "-c", &script])
+            .output()
+            .unwrap()
+            .stdout
+    }
+
+    #[test]
+    fn round_trips_every_byte() {
+        (0..=u8::MAX).for_each(|byte| assert_round_trip(&var(&[b'a', byte, b'z'])));
+    }
+
+    #[test]
+    fn round_trips_random_values() {
+        (0..1000).for_each(|seed| assert_round_trip(&var(&random_bytes(seed, seed as usize % 64))));
+    }
+
+    #[test]
+    fn round_trips_quotes_and_backslashes() {
+        ["", "'", "''", "\\", "a\\", "\\'", "$'x'", "\"${HOME}\"", "a b", " # x='y'"]
+            .iter()
+            .for_each(|value| assert_round_trip(&var(value.as_bytes())));
+    }
+
+    #[test]
+    fn round_trips_actions_and_attributes() {
+        let actions = [EncodeAction::Default, EncodeAction::Unexport, EncodeAction::Readonly];
+        let attributes = [None, Some(Attribute::Integer), Some(Attribute::Lower), Some(Attribute::Upper)];
+        for action in actions {
+            for attribute in attributes {
+                let var = EnvironmentVariable { attribute, ..var(b"1").with_action(action) };
+                let decoded = EnvironmentVariable::from_line(&var.encode()).unwrap();
+                assert_eq!(decoded.action, action);
+                assert_eq!(decoded.attribute, attribute);
+                assert_round_trip(&var);
+            }
+        }
+
+        let unset = EnvironmentVariable::from_line(&var(b"").with_action(EncodeAction::Unset).encode()).unwrap();
+        assert_eq!(unset.action, EncodeAction::Unset);
+    }
+
+    #[test]
+    fn round_trips_templates_and_conditions() {
+        let var = var(b"${HOME}/a\\$b'c${USER}")
+            .with_template(true)
+            .with_conditions(&[Condition::new("host", "build-*").unwrap(), Condition::new("os", "linux").unwrap()]);
+        let decoded = EnvironmentVariable::from_line(&var.encode()).unwrap();
+        assert!(decoded.template);
+        assert_eq!(decoded.conditions, var.conditions);
+        assert_round_trip(&var);
+    }
+
+    #[test]
+    fn round_trips_annotations() {
+        let lines = [
+            "declare -gx A='v' # command='echo hi' refresh='60' updated='100'",
+            "declare -gx A='v' # builtin='ssh-auth-sock' updated='100' description=$'it\\'s' tags='a,b' owner='me'",
+            "unset A # description='gone'",
+        ];
+        lines.iter()
+            .for_each(|line| assert_eq!(EnvironmentVariable::from_line(line).unwrap().encode(), *line));
+    }
+
+    #[test]
+    fn rejects_other_lines() {
+        let lines = [
+            "export A=1",
+            "declare -gx A=1",
+            "declare -gx A='1'; touch /tmp/x",
+            "declare -gx A='1' # unknown='x'",
+            "declare -gx A='1' # refresh='1'",
+            "declare -gx A=\"$(touch /tmp/x)\"",
+            "declare -gx 1A='1'",
+            "declare -gix A='a[$(touch /tmp/x)]'",
+            "[[ $(id) == x ]] && declare -gx A='1'",
+        ];
+        lines.iter()
+            .for_each(|line| assert!(EnvironmentVariable::from_line(line).is_err(), "{line}"));
+    }
+
+    #[test]
+    fn bash_sources_encoded_values() {
+        (0..200)
+            .map(|seed| {
+                // shell variables cannot hold NUL
+                random_bytes(seed, seed as usize % 48)
+                    .into_iter()
+                    .filter(|&byte| byte != 0)
+                    .collect::<Vec<_>>()
+            })
+            .chain([b"it's \"$HOME\" `id` \\\n".to_vec()])
+            .for_each(|value| assert_eq!(sourced(&var(&value)), value, "{}", var(&value).encode()));
+    }
+}
@@ -0,0 +1,296 @@
+use crate::str;
+use crate::utils::{now, print_error};
+use super::EnvironmentVariable;
+use super::encode::EncodeAction;
+use super::writer::{WriteManager, WriterError};
+use std::env;
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::io::ErrorKind;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use clap::ValueEnum;
+
+/// Name of the variable holding the earliest time a computed variable goes stale, the shell hook
+/// runs `gexport --recompute` once it has passed.
+const REFRESH_AT: &str = "GEXPORT_REFRESH_AT";
+
+/// Cached result of a producer, stored alongside the value.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) struct Computed {
+    pub(super) producer: Producer,
+    pub(super) refresh: Option<u64>,
+    updated: u64,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) enum Producer {
+    Command(Vec<u8>),
+    Builtin(Builtin),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, ValueEnum)]
+pub(crate) enum Builtin {
+    /// Socket of a running ssh-agent, gpg-agent or keyring.
+    SshAuthSock,
+}
+
+impl EnvironmentVariable {
+    /// Treats the value as producer, either a command line or the name of a builtin, and replaces it
+    /// with the output of its first run.
+    pub(crate) fn with_producer(self, builtin: bool, refresh: Option<u64>) -> Result<Self, ProducerError> {
+        let producer = Producer::new(builtin, &self.value)?;
+        let value = producer.run()?;
+        Ok(Self {
+            value,
+            computed: Some(Computed {
+                producer,
+                refresh,
+                updated: now(),
+            }),
+            ..self
+        })
+    }
+
+    /// What computes the variable, `None` if it isn't computed.
+    pub(crate) fn producer(&self) -> Option<&Producer> {
+        self.computed.as_ref().map(|computed| &computed.producer)
+    }
+
+    /// Whether the variable is managed by gexport itself rather than the user.
+    pub(crate) fn is_internal(&self) -> bool {
+        self.name == REFRESH_AT
+    }
+
+    /// The variable without what only holds on the machine it was computed on, i.e. the value of a
+    /// computed variable and the time it was computed.
+    pub(crate) fn portable(&self) -> Self {
+        match &self.computed {
+            Some(computed) => Self {
+                value: Vec::new(),
+                computed: Some(Computed { updated: 0, ..computed.clone() }),
+                ..self.clone()
+            },
+            None => self.clone(),
+        }
+    }
+
+    fn is_stale(&self, now: u64) -> bool {
+        self.stale_at().is_some_and(|stale_at| stale_at <= now)
+    }
+
+    fn stale_at(&self) -> Option<u64> {
+        self.computed
+            .as_ref()
+            .and_then(|computed| Some(computed.updated.saturating_add(computed.refresh?)))
+    }
+}
+
+impl Computed {
+    pub(crate) fn annotations(&self) -> Vec<(&'static str, Vec<u8>)> {
+        let key = match &self.producer {
+            Producer::Command(_) => "command",
+            Producer::Builtin(_) => "builtin",
+        };
+        let mut annotations = vec![(key, self.producer.source())];
+        if let Some(refresh) = self.refresh {
+            annotations.push(("refresh", refresh.to_string().into_bytes()));
+        }
+        annotations.push(("updated", self.updated.to_string().into_bytes()));
+        annotations
+    }
+
+    /// Inverse of `annotations`, fails on unknown or missing keys.
+    pub(crate) fn from_annotations(annotations: &[(String, Vec<u8>)]) -> Option<Option<Self>> {
+        let mut producer = None;
+        let mut refresh = None;
+        let mut updated = None;
+
+        for (key, value) in annotations {
+            let value = String::from_utf8_lossy(value);
+            match key.as_str() {
+                "command" => producer = Some(Producer::Command(value.as_bytes().to_vec())),
+                "builtin" => producer = Some(Producer::Builtin(Builtin::from_str(&value, false).ok()?)),
+                "refresh" => refresh = Some(value.parse().ok()?),
+                "updated" => updated = Some(value.parse().ok()?),
+                _ => return None,
+            }
+        }
+
+        match (producer, updated) {
+            (Some(producer), Some(updated)) => Some(Some(Self { producer, refresh, updated })),
+            (None, None) if refresh.is_none() => Some(None),
+            _ => None,
+        }
+    }
+}
+
+impl Producer {
+    pub(crate) fn new(builtin: bool, source: &[u8]) -> Result<Self, ProducerError> {
+        if builtin {
+            let name = String::from_utf8_lossy(source);
+            Builtin::from_str(&name, true)
+                .map(Self::Builtin)
+                .map_err(|_| ProducerError::UnknownBuiltin(name.to_string()))
+        } else {
+            Ok(Self::Command(source.to_vec()))
+        }
+    }
+
+    /// The command line or name of the builtin, as given on creation.
+    pub(crate) fn source(&self) -> Vec<u8> {
+        match self {
+            Self::Command(command) => command.clone(),
+            Self::Builtin(builtin) => {
+                builtin.to_possible_value().unwrap().get_name().as_bytes().to_vec()
+            },
+        }
+    }
+
+    fn run(&self) -> Result<Vec<u8>, ProducerError> {
+        let mut output = match self {
+            Self::Command(command) => {
+                let output = Command::new("sh")
+                    .arg("-c")
+                    .arg(OsStr::from_bytes(command))
+                    .stdin(Stdio::null())
+                    .stderr(Stdio::inherit())
+                    .output()?;
+                if !output.status.success() {
+                    return Err(ProducerError::Failed(String::from_utf8_lossy(command).to_string()));
+                }
+                output.stdout
+            },
+            Self::Builtin(Builtin::SshAuthSock) => {
+                find_ssh_auth_sock()
+                    .map(|socket| socket.into_os_string().as_bytes().to_vec())
+                    .ok_or(ProducerError::NotFound(str!("ssh agent socket")))?
+            },
+        };
+
+        // same as command substitution in the shell
+        while output.last() == Some(&b'\n') {
+            output.pop();
+        }
+        Ok(output)
+    }
+}
+
+fn find_ssh_auth_sock() -> Option<PathBuf> {
+    let runtime = env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from);
+    let known = env::var_os("SSH_AUTH_SOCK")
+        .map(PathBuf::from)
+        .into_iter()
+        .chain(runtime.into_iter().flat_map(|runtime| {
+            ["ssh-agent.socket", "ssh-agent", "gcr/ssh", "keyring/ssh", "gnupg/S.gpg-agent.ssh"]
+                .map(|socket| runtime.join(socket))
+        }));
+
+    // default location of sockets created by `ssh-agent`
+    let agents = fs::read_dir(env::temp_dir())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.file_name().as_bytes().starts_with(b"ssh-"))
+        .flat_map(|entry| fs::read_dir(entry.path()).into_iter().flatten().flatten())
+        .filter(|entry| entry.file_name().as_bytes().starts_with(b"agent."))
+        .map(|entry| entry.path());
+
+    known.chain(agents)
+        .find(|socket| UnixStream::connect(socket).is_ok())
+}
+
+/// Reruns the producers of all stale computed variables.
+pub(crate) fn recompute() -> Result<(), WriterError> {
+    // another invocation is writing the store, the shells recompute once it is done if still stale
+    let Some(_lock) = WriteManager::try_lock()? else {
+        return Ok(());
+    };
+    let now = now();
+
+    let stale = WriteManager::get()?
+        .iter()
+        .filter_map(|line| EnvironmentVariable::from_line(line).ok())
+        .filter(|var| var.is_stale(now))
+        .collect::<Vec<_>>();
+
+    stale.into_iter()
+        .try_for_each(|mut var| {
+            match var.computed.as_ref().unwrap().producer.run() {
+                Ok(value) => var.value = value,
+                Err(error) => print_error(&format!("cannot recompute '{}', {error}", var.name)),
+            }
+            // also on failure, so a broken producer is only retried once it is stale again
+            var.computed.as_mut().unwrap().updated = now;
+            var.update()
+        })?;
+
+    WriteManager::finalize()
+}
+
+/// Updates the variable telling shells when to run `gexport --recompute` next.
+pub(crate) fn update_refresh_at() -> Result<(), WriterError> {
+    let refresh_at = WriteManager::get()?
+        .iter_pending()
+        .filter_map(|line| EnvironmentVariable::from_line(line).ok())
+        .filter_map(|var| var.stale_at())
+        .min();
+
+    let var = EnvironmentVariable::new(REFRESH_AT.as_bytes().to_vec())
+        .expect("valid identifier");
+    match refresh_at {
+        Some(refresh_at) => {
+            let var = EnvironmentVariable {
+                value: refresh_at.to_string().into_bytes(),
+                action: EncodeAction::Unexport,
+                ..var
+            };
+            if var.get_line()? != Some(var.encode()) {
+                var.update()?;
+            }
+        },
+        None => var.delete()?,
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub(crate) enum ProducerError {
+    Io(ErrorKind),
+    Failed(String),
+    NotFound(String),
+    UnknownBuiltin(String),
+}
+
+impl From<io::Error> for ProducerError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error.kind())
+    }
+}
+
+impl Display for ProducerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "cannot run producer, {error:?}"),
+            Self::Failed(command) => write!(f, "'{command}' failed"),
+            Self::NotFound(what) => write!(f, "no {what} found"),
+            Self::UnknownBuiltin(name) => {
+                let builtins = Builtin::value_variants()
+                    .iter()
+                    .filter_map(|builtin| builtin.to_possible_value())
+                    .map(|value| value.get_name().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "'{name}' is not a builtin, expected one of {builtins}")
+            },
+        }
+    }
+}
+
+impl Error for ProducerError {}
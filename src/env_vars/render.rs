@@ -0,0 +1,123 @@
+mod csh;
+mod elvish;
+mod nushell;
+mod posix;
+mod powershell;
+mod xonsh;
+
+use crate::args::Shell;
+use crate::utils::print_error;
+use super::EnvironmentVariable;
+use super::encode::EncodeAction;
+use super::template;
+use super::template::Segment;
+use super::layer::Layer;
+use super::tombstone;
+use super::writer::WriterError;
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+use std::fmt::Display;
+use std::os::unix::ffi::OsStringExt;
+
+/// Renders the stored variables which apply to the calling shell as commands of the given shell,
+/// for shells which cannot source the store themselves.
+pub(crate) fn render(shell: Shell) -> Result<String, WriterError> {
+    let layers = Layer::all()?;
+    let vars = tombstone::settle(&layers)
+        .into_iter()
+        .chain(layers.iter().flat_map(Layer::vars))
+        .collect::<Vec<_>>();
+
+    Ok(encode(shell, &vars))
+}
+
+/// Renders only the commands recording the prior values of the stored variables and restoring
+/// deleted ones, for shells sourcing the store themselves.
+pub(crate) fn render_tombstones(shell: Shell) -> Result<String, WriterError> {
+    Ok(encode(shell, &tombstone::settle(&Layer::all()?)))
+}
+
+fn encode(shell: Shell, vars: &[EnvironmentVariable]) -> String {
+    match shell {
+        Shell::Bash | Shell::Zsh => lines(vars, EnvironmentVariable::encode),
+        Shell::Sh => lines(vars, EnvironmentVariable::encode_posix),
+        Shell::Nushell => nushell::render(vars),
+        Shell::Powershell => lines(vars, EnvironmentVariable::encode_powershell),
+        Shell::Tcsh => {
+            // variables which cannot be represented are left out, the others are still applied
+            let mut resolved = Resolved::default();
+            vars.iter()
+                .filter_map(|var| {
+                    var.encode_csh(&mut resolved)
+                        .inspect_err(print_error).ok()
+                })
+                .map(|line| line + "\n")
+                .collect()
+        },
+        Shell::Elvish => lines(vars, EnvironmentVariable::encode_elvish),
+        Shell::Xonsh => {
+            let mut resolved = Resolved::default();
+            vars.iter()
+                .map(|var| var.encode_xonsh(&mut resolved) + "\n")
+                .collect()
+        },
+    }
+}
+
+fn lines(vars: &[EnvironmentVariable], encode: fn(&EnvironmentVariable) -> String) -> String {
+    vars.iter()
+        .map(|var| encode(var) + "\n")
+        .collect()
+}
+
+impl EnvironmentVariable {
+    /// Splits the value into literals and references, the latter only for templates.
+    fn segments(&self) -> Vec<Segment> {
+        if self.template {
+            template::parse(&self.value)
+        } else {
+            vec![Segment::Literal(self.value.clone())]
+        }
+    }
+}
+
+/// Values of the variables rendered so far, for shells whose templates are resolved by gexport
+/// rather than the shell.
+#[derive(Default)]
+struct Resolved {
+    values: HashMap<String, Option<Vec<u8>>>,
+}
+
+impl Resolved {
+    /// Resolves the value of the variable as the shell would at this point, `None` if it is unset.
+    fn resolve(&mut self, var: &EnvironmentVariable) -> Option<Vec<u8>> {
+        let value = (var.action != EncodeAction::Unset).then(|| {
+            var.resolve(|name| {
+                self.values
+                    .get(name)
+                    .cloned()
+                    .unwrap_or_else(|| env::var_os(name).map(OsString::into_vec))
+            })
+        });
+        self.values.insert(var.name.clone(), value.clone());
+        value
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum RenderError {
+    Unrepresentable(String, &'static str),
+}
+
+impl Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unrepresentable(name, reason) => write!(f, "cannot render '{name}', {reason}"),
+        }
+    }
+}
+
+impl Error for RenderError {}
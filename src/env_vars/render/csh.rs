@@ -0,0 +1,76 @@
+use crate::str;
+use super::{RenderError, Resolved};
+use super::super::EnvironmentVariable;
+use super::super::encode::EncodeAction;
+
+impl EnvironmentVariable {
+    /// Encodes the variable for csh and tcsh. Templates are resolved here, since referencing an
+    /// undefined variable is an error in csh. Read-only and typed variables have no equivalent.
+    pub(super) fn encode_csh(&self, resolved: &mut Resolved) -> Result<String, RenderError> {
+        let name = &self.name;
+        let Some(value) = resolved.resolve(self) else {
+            return Ok(format!("unsetenv {name}; unset {name}"));
+        };
+        let value = quote(&value)
+            .map_err(|reason| RenderError::Unrepresentable(name.clone(), reason))?;
+
+        Ok(match self.action {
+            EncodeAction::Unexport => format!("unsetenv {name}; set {name} = {value}"),
+            _ => format!("setenv {name} {value}"),
+        })
+    }
+}
+
+/// Single quotes the value, where `!` has to be escaped outside of the quotes to prevent history
+/// substitution. Other control characters than newlines are produced by printf, newlines cannot
+/// be written in a way all csh variants agree on.
+fn quote(input: &[u8]) -> Result<String, &'static str> {
+    let input = std::str::from_utf8(input).map_err(|_| "the value is not valid UTF-8")?;
+    let mut quoted = String::new();
+    let mut literal = String::new();
+    let flush = |quoted: &mut String, literal: &mut String| {
+        if !literal.is_empty() {
+            quoted.push_str(&format!("'{literal}'"));
+            literal.clear();
+        }
+    };
+
+    for char in input.chars() {
+        match char {
+            '\n' => return Err("csh cannot represent newlines in values"),
+            '\'' | '!' => {
+                flush(&mut quoted, &mut literal);
+                quoted.push('\\');
+                quoted.push(char);
+            },
+            char if char.is_ascii_control() => {
+                flush(&mut quoted, &mut literal);
+                quoted.push_str(&format!("\"`printf '\\{:03o}'`\"", char as u8));
+            },
+            char => literal.push(char),
+        }
+    }
+    flush(&mut quoted, &mut literal);
+
+    Ok(if quoted.is_empty() { str!("''") } else { quoted })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_special_characters() {
+        assert_eq!(quote(b"it's").unwrap(), r"'it'\''s'");
+        assert_eq!(quote(b"\"$HOME\" \\").unwrap(), "'\"$HOME\" \\'");
+        assert_eq!(quote(b"hi!").unwrap(), r"'hi'\!");
+        assert_eq!(quote(b"a\tb").unwrap(), "'a'\"`printf '\\011'`\"'b'");
+        assert_eq!(quote(b"").unwrap(), "''");
+    }
+
+    #[test]
+    fn refuses_unrepresentable_values() {
+        assert!(quote(b"a\nb").is_err());
+        assert!(quote(b"\xff").is_err());
+    }
+}
@@ -0,0 +1,76 @@
+use crate::str;
+use super::super::EnvironmentVariable;
+use super::super::encode::EncodeAction;
+use super::super::template::Segment;
+
+impl EnvironmentVariable {
+    /// Encodes the variable for elvish. Elvish exports all of its environment, so unexported
+    /// variables are set like the others. Read-only and typed variables have no equivalent.
+    pub(super) fn encode_elvish(&self) -> String {
+        let name = &self.name;
+        let value = self.segments()
+            .iter()
+            .map(|segment| {
+                match segment {
+                    Segment::Literal(literal) => quote(literal),
+                    // unset variables of the E: namespace are empty instead of an error
+                    Segment::Reference(name) => format!("$E:{name}"),
+                }
+            })
+            .collect::<String>();
+        let value = if value.is_empty() { str!("''") } else { value };
+
+        match self.action {
+            EncodeAction::Unset => format!("unset-env {name}"),
+            _ => format!("set-env {name} {value}"),
+        }
+    }
+}
+
+/// Single quotes the value, control characters and invalid UTF-8 are written as `\xHH` escapes in
+/// double quotes. Adjacent quoted strings form a single word.
+fn quote(input: &[u8]) -> String {
+    let mut quoted = String::new();
+    let mut literal = String::new();
+    let flush = |quoted: &mut String, literal: &mut String| {
+        if !literal.is_empty() {
+            quoted.push_str(&format!("'{}'", literal.replace('\'', "''")));
+            literal.clear();
+        }
+    };
+    let escape = |quoted: &mut String, bytes: &[u8]| {
+        let escaped = bytes.iter().map(|byte| format!("\\x{byte:02x}")).collect::<String>();
+        quoted.push_str(&format!("\"{escaped}\""));
+    };
+
+    for chunk in input.utf8_chunks() {
+        for char in chunk.valid().chars() {
+            if char.is_ascii_control() {
+                flush(&mut quoted, &mut literal);
+                escape(&mut quoted, &[char as u8]);
+            } else {
+                literal.push(char);
+            }
+        }
+        if !chunk.invalid().is_empty() {
+            flush(&mut quoted, &mut literal);
+            escape(&mut quoted, chunk.invalid());
+        }
+    }
+    flush(&mut quoted, &mut literal);
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_special_characters() {
+        assert_eq!(quote(b"it's"), "'it''s'");
+        assert_eq!(quote(b"\"$HOME\" \\"), "'\"$HOME\" \\'");
+        assert_eq!(quote(b"a\nb"), "'a'\"\\x0a\"'b'");
+        assert_eq!(quote(b"\xffa"), "\"\\xff\"'a'");
+        assert_eq!(quote(b""), "");
+    }
+}
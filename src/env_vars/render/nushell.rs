@@ -0,0 +1,67 @@
+use super::Resolved;
+use super::super::EnvironmentVariable;
+
+/// Renders the variables as JSON record of the values for `load-env` and the names for `hide-env`,
+/// as nushell cannot evaluate commands generated at runtime.
+///
+/// Templates are resolved here, values that are not valid UTF-8 are converted lossily the same way
+/// nushell does with its own environment. Nushell exports all of its environment, so unexported
+/// variables are loaded like the others.
+pub(super) fn render(vars: &[EnvironmentVariable]) -> String {
+    let mut resolved = Resolved::default();
+    let mut load: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut hide: Vec<String> = Vec::new();
+
+    for var in vars {
+        let value = resolved.resolve(var);
+        load.retain(|(name, _)| *name != var.name);
+        hide.retain(|name| *name != var.name);
+        match value {
+            Some(value) => load.push((var.name.clone(), value)),
+            None => hide.push(var.name.clone()),
+        }
+    }
+
+    let load = load
+        .iter()
+        .map(|(name, value)| format!("{}:{}", quote(name), quote(&String::from_utf8_lossy(value))))
+        .collect::<Vec<_>>()
+        .join(",");
+    let hide = hide
+        .iter()
+        .map(|name| quote(name))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("{{\"load\":{{{load}}},\"hide\":[{hide}]}}\n")
+}
+
+fn quote(input: &str) -> String {
+    let mut quoted = String::with_capacity(input.len() + 2);
+    quoted.push('"');
+    for char in input.chars() {
+        match char {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            char if char.is_control() => quoted.push_str(&format!("\\u{:04x}", char as u32)),
+            char => quoted.push(char),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_special_characters() {
+        assert_eq!(quote("it's"), "\"it's\"");
+        assert_eq!(quote("\"$HOME\" \\"), "\"\\\"$HOME\\\" \\\\\"");
+        assert_eq!(quote("a\nb\t\x01"), "\"a\\nb\\t\\u0001\"");
+        assert_eq!(quote(""), "\"\"");
+    }
+}
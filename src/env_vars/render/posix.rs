@@ -0,0 +1,107 @@
+use crate::str;
+use super::super::EnvironmentVariable;
+use super::super::encode::EncodeAction;
+use super::super::template::Segment;
+
+impl EnvironmentVariable {
+    /// Encodes the variable for POSIX sh. Attributes other than read-only have no equivalent, the
+    /// value was already converted when stored.
+    pub(super) fn encode_posix(&self) -> String {
+        let name = &self.name;
+        let value = self.segments()
+            .iter()
+            .map(|segment| {
+                match segment {
+                    Segment::Literal(literal) => quote(literal),
+                    Segment::Reference(name) => format!("\"${{{name}}}\""),
+                }
+            })
+            .collect::<String>();
+        let value = if value.is_empty() { str!("''") } else { value };
+
+        match self.action {
+            EncodeAction::Default => format!("export {name}={value}"),
+            // there is no way to unexport a variable other than to unset it first
+            EncodeAction::Unexport => format!("unset {name}; {name}={value}"),
+            EncodeAction::Unset => format!("unset {name}"),
+            // assigning to a read-only variable aborts the whole render, so it is tried in a subshell
+            EncodeAction::Readonly => {
+                format!("({name}=) 2>/dev/null && export {name}={value} && readonly {name}")
+            },
+        }
+    }
+}
+
+/// Single quotes the value, control characters and invalid UTF-8 are produced by printf as they
+/// can't be written portably otherwise. Newlines are kept literally, command substitution would
+/// strip them.
+fn quote(input: &[u8]) -> String {
+    let mut quoted = String::new();
+    let mut literal = String::new();
+    let flush = |quoted: &mut String, literal: &mut String| {
+        if !literal.is_empty() {
+            quoted.push_str(&format!("'{}'", literal.replace('\'', r"'\''")));
+            literal.clear();
+        }
+    };
+    let printf = |quoted: &mut String, bytes: &[u8]| {
+        bytes.iter().for_each(|byte| quoted.push_str(&format!("\"$(printf '\\{byte:03o}')\"")));
+    };
+
+    for chunk in input.utf8_chunks() {
+        for char in chunk.valid().chars() {
+            if char.is_ascii_control() && char != '\n' && char != '\t' {
+                flush(&mut quoted, &mut literal);
+                printf(&mut quoted, &[char as u8]);
+            } else {
+                literal.push(char);
+            }
+        }
+        if !chunk.invalid().is_empty() {
+            flush(&mut quoted, &mut literal);
+            printf(&mut quoted, chunk.invalid());
+        }
+    }
+    flush(&mut quoted, &mut literal);
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env_vars::random_bytes;
+    use std::process::Command;
+
+    /// Value sh assigns when evaluating the quoted value.
+    fn evaluated(quoted: &str) -> Vec<u8> {
+        Command::new("sh")
+            .args(["-c", &format!("NAME={quoted}\nprintf %s \"$NAME\"")])
+            .output()
+            .unwrap()
+            .stdout
+    }
+
+    #[test]
+    fn quotes_special_characters() {
+        assert_eq!(quote(b"it's"), r"'it'\''s'");
+        assert_eq!(quote(b"\"$HOME\" \\ `id`"), "'\"$HOME\" \\ `id`'");
+        assert_eq!(quote(b"a\nb"), "'a\nb'");
+        assert_eq!(quote(b"a\x1bb"), "'a'\"$(printf '\\033')\"'b'");
+        assert_eq!(quote(b""), "");
+    }
+
+    #[test]
+    fn sh_evaluates_quoted_values() {
+        (0..200)
+            .map(|seed| {
+                // shell variables cannot hold NUL, command substitution strips trailing newlines
+                random_bytes(seed, seed as usize % 48)
+                    .into_iter()
+                    .filter(|&byte| byte != 0)
+                    .collect::<Vec<_>>()
+            })
+            .chain([b"it's \"$HOME\" `id` \\\nx".to_vec()])
+            .filter(|value| !value.is_empty())
+            .for_each(|value| assert_eq!(evaluated(&quote(&value)), value, "{}", quote(&value)));
+    }
+}
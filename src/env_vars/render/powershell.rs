@@ -0,0 +1,95 @@
+use crate::str;
+use super::super::EnvironmentVariable;
+use super::super::encode::EncodeAction;
+use super::super::template::Segment;
+
+impl EnvironmentVariable {
+    /// Encodes the variable for PowerShell. Environment variables can't be read-only or typed
+    /// there, unexported ones become global variables of the session. Values that are not valid
+    /// UTF-8 are converted lossily.
+    pub(super) fn encode_powershell(&self) -> String {
+        let name = &self.name;
+        let segments = self.segments();
+        let mut value = segments
+            .iter()
+            .map(|segment| {
+                match segment {
+                    Segment::Literal(literal) => quote(&String::from_utf8_lossy(literal)),
+                    Segment::Reference(name) => format!("$env:{name}"),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" + ");
+        // concatenating starts from a string, even if the first reference is not set
+        if !matches!(segments.first(), Some(Segment::Literal(_))) {
+            value = if value.is_empty() { str!("''") } else { format!("'' + {value}") };
+        }
+
+        match self.action {
+            EncodeAction::Default | EncodeAction::Readonly => format!("$env:{name} = {value}"),
+            EncodeAction::Unexport => {
+                format!("Remove-Item Env:{name} -ErrorAction SilentlyContinue; $global:{name} = {value}")
+            },
+            EncodeAction::Unset => format!("Remove-Item Env:{name} -ErrorAction SilentlyContinue"),
+        }
+    }
+}
+
+/// Single quotes the value, doubling quotes including the typographic ones PowerShell accepts as
+/// well. Control characters are written as backtick escapes in double quotes.
+fn quote(input: &str) -> String {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut escaped = String::new();
+
+    for char in input.chars() {
+        if char.is_control() {
+            if !literal.is_empty() {
+                parts.push(format!("'{}'", std::mem::take(&mut literal)));
+            }
+            match char {
+                '\0' => escaped.push_str("`0"),
+                '\x07' => escaped.push_str("`a"),
+                '\x08' => escaped.push_str("`b"),
+                '\t' => escaped.push_str("`t"),
+                '\n' => escaped.push_str("`n"),
+                '\x0B' => escaped.push_str("`v"),
+                '\x0C' => escaped.push_str("`f"),
+                '\r' => escaped.push_str("`r"),
+                '\x1B' => escaped.push_str("`e"),
+                char => escaped.push_str(&format!("`u{{{:x}}}", char as u32)),
+            }
+        } else {
+            if !escaped.is_empty() {
+                parts.push(format!("\"{}\"", std::mem::take(&mut escaped)));
+            }
+            if matches!(char, '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}') {
+                literal.push(char);
+            }
+            literal.push(char);
+        }
+    }
+    if !literal.is_empty() {
+        parts.push(format!("'{literal}'"));
+    }
+    if !escaped.is_empty() {
+        parts.push(format!("\"{escaped}\""));
+    }
+
+    if parts.is_empty() { str!("''") } else { parts.join(" + ") }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_special_characters() {
+        assert_eq!(quote("it's"), "'it''s'");
+        assert_eq!(quote("it\u{2019}s"), "'it\u{2019}\u{2019}s'");
+        assert_eq!(quote("\"$HOME\" \\ `"), "'\"$HOME\" \\ `'");
+        assert_eq!(quote("a\nb"), "'a' + \"`n\" + 'b'");
+        assert_eq!(quote("\x01"), "\"`u{1}\"");
+        assert_eq!(quote(""), "''");
+    }
+}
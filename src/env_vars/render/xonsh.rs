@@ -0,0 +1,53 @@
+use super::Resolved;
+use super::super::EnvironmentVariable;
+
+impl EnvironmentVariable {
+    /// Encodes the variable for xonsh. Templates are resolved here, as xonsh types some of its
+    /// environment, e.g. `$PATH` is a list. Xonsh exports all of its environment, so unexported
+    /// variables are set like the others.
+    pub(super) fn encode_xonsh(&self, resolved: &mut Resolved) -> String {
+        let name = &self.name;
+        match resolved.resolve(self) {
+            Some(value) => format!("${name} = {}", quote(&value)),
+            None => format!("${{...}}.pop('{name}', None)"),
+        }
+    }
+}
+
+/// Quotes the value as Python string. Invalid UTF-8 is escaped as lone surrogates, which Python
+/// turns back into the original bytes when passing the environment on.
+fn quote(input: &[u8]) -> String {
+    let mut quoted = String::from("'");
+    for chunk in input.utf8_chunks() {
+        for char in chunk.valid().chars() {
+            match char {
+                '\\' => quoted.push_str("\\\\"),
+                '\'' => quoted.push_str("\\'"),
+                '\n' => quoted.push_str("\\n"),
+                '\t' => quoted.push_str("\\t"),
+                '\r' => quoted.push_str("\\r"),
+                char if char.is_control() => quoted.push_str(&format!("\\u{:04x}", char as u32)),
+                char => quoted.push(char),
+            }
+        }
+        chunk.invalid()
+            .iter()
+            .for_each(|byte| quoted.push_str(&format!("\\udc{byte:02x}")));
+    }
+    quoted.push('\'');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_special_characters() {
+        assert_eq!(quote(b"it's"), r"'it\'s'");
+        assert_eq!(quote(b"\"$HOME\" \\"), r#"'"$HOME" \\'"#);
+        assert_eq!(quote(b"a\nb\x01"), r"'a\nb\u0001'");
+        assert_eq!(quote(b"\xff"), r"'\udcff'");
+        assert_eq!(quote(b""), "''");
+    }
+}
@@ -0,0 +1,145 @@
+use super::EnvironmentVariable;
+
+/// Part of a template value, either literal bytes or a reference to another variable.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) enum Segment {
+    Literal(Vec<u8>),
+    Reference(String),
+}
+
+impl EnvironmentVariable {
+    /// Value as assigned by the shell, references are resolved using `lookup`.
+    pub(crate) fn resolve<F: Fn(&str) -> Option<Vec<u8>>>(&self, lookup: F) -> Vec<u8> {
+        if !self.template {
+            return self.value.clone();
+        }
+
+        parse(&self.value)
+            .into_iter()
+            .flat_map(|segment| {
+                match segment {
+                    Segment::Literal(literal) => literal,
+                    Segment::Reference(name) => lookup(&name).unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Splits a template such as `$HOME/.kube/${CLUSTER}` into its segments.
+///
+/// References are written as `$NAME` or `${NAME}`, `\$` and `\\` escape a literal `$` and `\`. A `$`
+/// not followed by an identifier is kept literally.
+pub(crate) fn parse(template: &[u8]) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut literal = Vec::new();
+    let mut index = 0;
+
+    while index < template.len() {
+        let byte = template[index];
+        match (byte, template.get(index + 1)) {
+            (b'\\', Some(&escaped @ (b'$' | b'\\'))) => {
+                literal.push(escaped);
+                index += 2;
+            },
+            (b'$', Some(_)) => {
+                let braced = template[index + 1] == b'{';
+                let start = index + 1 + braced as usize;
+                let end = template[start..]
+                    .iter()
+                    .position(|&byte| !(byte.is_ascii_alphanumeric() || byte == b'_'))
+                    .map_or(template.len(), |end| start + end);
+                let name = String::from_utf8_lossy(&template[start..end]).to_string();
+                let closed = !braced || template.get(end) == Some(&b'}');
+
+                if closed && EnvironmentVariable::is_identifier_valid(&name) {
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Reference(name));
+                    index = end + braced as usize;
+                } else {
+                    literal.push(byte);
+                    index += 1;
+                }
+            },
+            _ => {
+                literal.push(byte);
+                index += 1;
+            },
+        }
+    }
+
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+    segments
+}
+
+/// Inverse of `parse`, writes references in their braced form.
+pub(crate) fn source(segments: &[Segment]) -> Vec<u8> {
+    let mut source = Vec::new();
+    segments.iter()
+        .for_each(|segment| {
+            match segment {
+                Segment::Literal(literal) => {
+                    literal.iter()
+                        .for_each(|&byte| {
+                            if byte == b'$' || byte == b'\\' {
+                                source.push(b'\\');
+                            }
+                            source.push(byte);
+                        });
+                },
+                Segment::Reference(name) => {
+                    source.extend_from_slice(format!("${{{name}}}").as_bytes());
+                },
+            }
+        });
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn literal(literal: &str) -> Segment {
+        Segment::Literal(literal.as_bytes().to_vec())
+    }
+
+    fn reference(name: &str) -> Segment {
+        Segment::Reference(name.to_string())
+    }
+
+    #[test]
+    fn parses_references() {
+        assert_eq!(parse(b"$HOME/.kube/${CLUSTER}"), [reference("HOME"), literal("/.kube/"), reference("CLUSTER")]);
+        assert_eq!(parse(b"${A}${B}"), [reference("A"), reference("B")]);
+        assert_eq!(parse(b"$A-b"), [reference("A"), literal("-b")]);
+    }
+
+    #[test]
+    fn keeps_other_dollars_literally() {
+        assert_eq!(parse(b"$"), [literal("$")]);
+        assert_eq!(parse(b"a$"), [literal("a$")]);
+        assert_eq!(parse(b"$1"), [literal("$1")]);
+        assert_eq!(parse(b"${A"), [literal("${A")]);
+        assert_eq!(parse(b"${}"), [literal("${}")]);
+        assert_eq!(parse(b"$ x"), [literal("$ x")]);
+        assert!(parse(b"").is_empty());
+    }
+
+    #[test]
+    fn unescapes_dollars_and_backslashes() {
+        assert_eq!(parse(b"\\$HOME"), [literal("$HOME")]);
+        assert_eq!(parse(b"\\\\$HOME"), [literal("\\"), reference("HOME")]);
+        assert_eq!(parse(b"a\\b\\"), [literal("a\\b\\")]);
+    }
+
+    #[test]
+    fn source_is_inverse_of_parse() {
+        ["${HOME}/x", "\\$HOME", "a\\\\b", "${A}\\$${B}", "plain"]
+            .iter()
+            .for_each(|template| assert_eq!(source(&parse(template.as_bytes())), template.as_bytes()));
+    }
+}
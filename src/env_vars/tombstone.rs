@@ -0,0 +1,111 @@
+use super::{EncodeAction, EnvironmentVariable};
+use super::layer::Layer;
+use crate::utils::now;
+use std::env;
+use std::os::unix::ffi::OsStringExt;
+use itertools::Itertools;
+
+/// Tombstones are comments, so the shells sourcing the env-var file ignore them.
+const PREFIX: &str = "# deleted ";
+
+/// Shells record the value a variable had before gexport first set it in a variable of this prefix,
+/// as `=VALUE` if it was set and empty if it was not. Exported, so nested shells restore the value
+/// of the outermost shell.
+const PRIOR: &str = "GEXPORT_PRIOR_";
+
+/// Seconds after which a tombstone is dropped, by then the shells which had the variable have
+/// sourced the env-var file and restored its prior value.
+const EXPIRY: u64 = 7 * 24 * 60 * 60;
+
+/// Line left in place of a deleted variable, shells which had it restore its prior value. Followed
+/// by the time of the deletion.
+pub(crate) fn encode(name: &str) -> String {
+    format!("{PREFIX}{name} {}", now())
+}
+
+/// Name of the deleted variable if the line is a tombstone.
+pub(crate) fn from_line(line: &str) -> Option<&str> {
+    line.strip_prefix(PREFIX)
+        .and_then(|rest| rest.split(' ').next())
+        .filter(|name| EnvironmentVariable::is_identifier_valid(name))
+}
+
+/// Whether the tombstone is old enough to be dropped, also if it lacks the time of the deletion.
+pub(crate) fn is_expired(line: &str, now: u64) -> bool {
+    line.rsplit_once(' ')
+        .and_then(|(_, deleted)| deleted.parse::<u64>().ok())
+        .is_none_or(|deleted| deleted.saturating_add(EXPIRY) <= now)
+}
+
+pub(crate) fn is_tombstone(line: &str) -> bool {
+    from_line(line).is_some()
+}
+
+/// Records the current values of the variables the layers are about to set, unless the calling
+/// shell already has, followed by restoring the variables the layers only hold tombstones of.
+pub(super) fn settle(layers: &[Layer]) -> Vec<EnvironmentVariable> {
+    let declared = layers.iter()
+        .flat_map(Layer::vars)
+        .filter(|var| !var.is_internal())
+        .map(|var| var.name)
+        .unique()
+        .collect::<Vec<_>>();
+
+    let record = declared.iter()
+        .filter(|name| env::var_os(prior(name)).is_none())
+        .filter_map(|name| {
+            let value = env::var_os(name)
+                .map(|value| [b"=".to_vec(), value.into_vec()].concat())
+                .unwrap_or_default();
+            declare(&prior(name), Some(value))
+        });
+
+    let restore = layers.iter()
+        .flat_map(|layer| layer.lines.iter())
+        .filter_map(|line| from_line(line))
+        .unique()
+        .filter(|name| !declared.iter().any(|declared| declared == name))
+        .filter_map(|name| Some((name, env::var_os(prior(name))?.into_vec())))
+        .flat_map(|(name, recorded)| {
+            let value = recorded.strip_prefix(b"=").map(<[u8]>::to_vec);
+            [declare(name, value), declare(&prior(name), None)]
+        })
+        .flatten();
+
+    record.chain(restore).collect()
+}
+
+fn prior(name: &str) -> String {
+    format!("{PRIOR}{name}")
+}
+
+/// Exports the variable with the given value, or unsets it.
+fn declare(name: &str, value: Option<Vec<u8>>) -> Option<EnvironmentVariable> {
+    let var = EnvironmentVariable::new(name.as_bytes().to_vec()).ok()?;
+    Some(match value {
+        Some(value) => EnvironmentVariable { value, ..var },
+        None => var.with_action(EncodeAction::Unset),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tombstones() {
+        assert_eq!(from_line("# deleted NAME 100"), Some("NAME"));
+        assert_eq!(from_line("# deleted NAME"), Some("NAME"));
+        assert_eq!(from_line(&encode("NAME")), Some("NAME"));
+        assert_eq!(from_line("# deleted 1NAME 100"), None);
+        assert_eq!(from_line("# NAME 100"), None);
+    }
+
+    #[test]
+    fn expires_tombstones() {
+        assert!(!is_expired("# deleted NAME 100", 100 + EXPIRY - 1));
+        assert!(is_expired("# deleted NAME 100", 100 + EXPIRY));
+        assert!(is_expired("# deleted NAME", 0));
+        assert!(!is_expired(&encode("NAME"), now()));
+    }
+}
@@ -1,19 +1,28 @@
 use crate::CLI;
 use crate::str;
+use crate::args::{ImportMode, Scope};
+use crate::utils::{confirm, now};
 use super::EnvironmentVariable;
 use super::encode::EncodeAction;
+use super::layer::Layer;
+use super::producer::update_refresh_at;
+use super::tombstone;
+use std::collections::HashSet;
+use std::env;
 use std::error::Error;
 use std::fmt;
 use std::fmt::Display;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use fs2::FileExt;
-use std::fs::OpenOptions;
+use std::fs::{File, OpenOptions};
 use std::fs;
 use std::io;
 use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
 use std::slice::Iter;
 use std::sync::{OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard};
-use regex::Regex;
+use std::time::SystemTime;
+use colored::Colorize;
+use itertools::Itertools;
 
 impl EnvironmentVariable {
     pub(crate) fn delete(&self) -> Result<(), WriterError> {
@@ -23,17 +32,23 @@ impl EnvironmentVariable {
         Ok(())
     }
 
+    /// Deletes the variable including all of its conditional variants.
+    pub(crate) fn delete_all(&self) -> Result<(), WriterError> {
+        WriteManager::get_mut()?.retain(|line| !self.is_variant(line, false));
+        Ok(())
+    }
+
     pub(crate) fn append(&self) -> Result<(), WriterError> {
-        let encode_action = if CLI.args.as_ref().unwrap().unset {
-            EncodeAction::Unset
-        } else if CLI.args.as_ref().unwrap().unexport {
-            EncodeAction::Unexport
-        } else {
-            EncodeAction::Default
-        };
-        let line = self.encode(encode_action);
+        let line = self.encode();
         
-        WriteManager::get_mut()?.append(line);
+        // conditional variants have to come after the unconditional value to take precedence, and
+        // templates after all plain values so their references are up to date when resolved
+        WriteManager::get_mut()?.insert_before(line, |line| {
+            EnvironmentVariable::from_line(line).is_ok_and(|other| {
+                (self.conditions.is_empty() && self.is_variant(line, true))
+                    || (!self.template && other.template)
+            })
+        });
         
         Ok(())
     }
@@ -48,23 +63,51 @@ impl EnvironmentVariable {
         )
     }
 
-    fn position(&self) -> Result<Option<usize>, WriterError> {
-        let var_line = Regex::new(
-            &format!("{}{}(?:[ =]|$)", Self::LINE_PATTERN, &self.name)
-        ).expect("regex pattern exceeded max size or is invalid");
+    /// Returns the stored variant of the variable with the same conditions.
+    pub(crate) fn stored(&self) -> Result<Option<Self>, WriterError> {
+        Ok(self.get_line()?.and_then(|line| Self::from_line(&line).ok()))
+    }
 
+    /// Replaces the stored line of the variable in place, or appends it if not yet stored.
+    pub(crate) fn update(&self) -> Result<(), WriterError> {
+        match self.position()? {
+            Some(position) => WriteManager::get_mut()?.replace_at(position, self.encode()),
+            None => self.append()?,
+        }
+        Ok(())
+    }
+
+    /// Returns the lines of the variable and all of its conditional variants.
+    pub(crate) fn get_lines(&self) -> Result<Vec<String>, WriterError> {
+        Ok(WriteManager::get()?
+            .iter()
+            .filter(|line| self.is_variant(line, false))
+            .cloned()
+            .collect())
+    }
+
+    fn position(&self) -> Result<Option<usize>, WriterError> {
         let write_manager = WriteManager::get()?;
         Ok(write_manager
             .iter()
             .position(|line| {
-                var_line.is_match(line)
+                EnvironmentVariable::from_line(line).is_ok_and(|other| {
+                    other.name == self.name && other.conditions == self.conditions
+                })
             })
         )
     }
+
+    fn is_variant(&self, line: &str, conditional: bool) -> bool {
+        EnvironmentVariable::from_line(line).is_ok_and(|other| {
+            other.name == self.name && (!conditional || !other.conditions.is_empty())
+        })
+    }
 }
 
 pub(crate) struct WriteManager {
     location: PathBuf,
+    original: Vec<String>,
     lines: Vec<String>,
     append: Vec<String>,
     rewrite: bool,
@@ -72,15 +115,27 @@ pub(crate) struct WriteManager {
 
 impl WriteManager {
     fn new() -> Result<Self, WriterError> {
-        let location = CLI.config_home.join("gexport");
-        fs::create_dir_all(&location)?;
+        let location = Self::store_location();
+        if let Some(parent) = location.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let lines = Self::read(&location)?;
         
-        let location = location.join("gexports");
+        Ok(Self {
+            location,
+            original: lines.clone(),
+            lines,
+            append: Vec::new(),
+            rewrite: false,
+        })
+    }
+    
+    fn read(location: &Path) -> Result<Vec<String>, WriterError> {
         let file = OpenOptions::new()
             .create(true)
             .read(true)
             .append(true)
-            .open(&location)?;
+            .open(location)?;
         
         let lines;
         FileExt::lock_shared(&file)?;
@@ -94,15 +149,15 @@ impl WriteManager {
                 .collect::<Vec<_>>();
         }
         FileExt::unlock(&file)?;
-        
-        Ok(Self {
-            location,
-            lines,
-            append: Vec::new(),
-            rewrite: false,
-        })
+        Ok(lines)
     }
-    
+
+    /// Whether the env-var file was changed by another invocation since it was first read.
+    pub(crate) fn is_outdated() -> Result<bool, WriterError> {
+        let this = Self::get()?;
+        Ok(Self::read(&this.location)? != this.original)
+    }
+
     fn get_mut() -> Result<RwLockWriteGuard<'static, Self>, WriterError> {
         Ok(Self::get_lock()?.write().unwrap())
     }
@@ -130,6 +185,11 @@ impl WriteManager {
         self.lines[index].clone()
     }
     
+    fn replace_at(&mut self, index: usize, item: String) {
+        self.lines[index] = item;
+        self.rewrite = true;
+    }
+
     fn delete(&mut self, index: usize) {
         self.lines.remove(index);
         self.rewrite = true;
@@ -138,9 +198,142 @@ impl WriteManager {
     fn append(&mut self, item: String) {
         self.append.push(item);
     }
+
+    fn insert_before<P: Fn(&str) -> bool>(&mut self, item: String, predicate: P) {
+        if let Some(index) = self.lines.iter().position(|line| predicate(line)) {
+            self.lines.insert(index, item);
+            self.rewrite = true;
+        } else if let Some(index) = self.append.iter().position(|line| predicate(line)) {
+            self.append.insert(index, item);
+        } else {
+            self.append(item);
+        }
+    }
+
+    /// Leaves a tombstone for each variable the pending changes remove entirely, so the shells
+    /// restore its prior value, and drops those of variables stored again or expired.
+    fn bury(&mut self) {
+        let remaining = self.lines
+            .iter()
+            .chain(&self.append)
+            .filter_map(|line| EnvironmentVariable::from_line(line).ok())
+            .map(|var| var.name)
+            .collect::<HashSet<_>>();
+        // variables stored or buried before, which are no longer stored
+        let now = now();
+        let buried = self.original
+            .iter()
+            .filter_map(|line| {
+                match EnvironmentVariable::from_line(line) {
+                    Ok(var) => (!var.is_internal()).then_some(var.name),
+                    Err(_) => tombstone::from_line(line)
+                        .filter(|_| !tombstone::is_expired(line, now))
+                        .map(str::to_string),
+                }
+            })
+            .filter(|name| !remaining.contains(name))
+            .unique()
+            .collect::<Vec<_>>();
+
+        self.retain(|line| tombstone::from_line(line).is_none_or(|name| buried.iter().any(|buried| buried == name)));
+        let kept = self.lines
+            .iter()
+            .chain(&self.append)
+            .filter_map(|line| tombstone::from_line(line))
+            .map(str::to_string)
+            .collect::<HashSet<_>>();
+        buried.into_iter()
+            .filter(|name| !kept.contains(name))
+            .for_each(|name| self.append(tombstone::encode(&name)));
+    }
+
+    /// Fails if the pending changes add a variable the system store locks, which the shells would
+    /// refuse, or touch one of which any stored variant is read-only, unless --force is given.
+    fn check_locked(&self) -> Result<(), WriterError> {
+        if !CLI.system {
+            let locked = Layer::system()?.locked_names();
+            let added = self.lines
+                .iter()
+                .chain(&self.append)
+                .filter(|line| !self.original.contains(line))
+                .filter_map(|line| EnvironmentVariable::from_line(line).ok())
+                .find(|var| locked.contains(&var.name));
+            if let Some(var) = added {
+                return Err(WriterError::SystemLocked(var.name));
+            }
+        }
+        if CLI.force {
+            return Ok(());
+        }
+
+        let variants = |lines: &mut dyn Iterator<Item = &String>, name: &str| {
+            lines.filter(|line| EnvironmentVariable::from_line(line).is_ok_and(|var| var.name == name))
+                .sorted()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        self.original
+            .iter()
+            .filter_map(|line| EnvironmentVariable::from_line(line).ok())
+            .filter(|var| var.action == EncodeAction::Readonly)
+            .map(|var| var.name)
+            .unique()
+            .find(|name| {
+                variants(&mut self.original.iter(), name) != variants(&mut self.lines.iter().chain(&self.append), name)
+            })
+            .map_or(Ok(()), |name| Err(WriterError::ReadOnly(name)))
+    }
+
+    fn retain<P: Fn(&str) -> bool>(&mut self, predicate: P) {
+        let len = self.lines.len();
+        self.lines.retain(|line| predicate(line));
+        self.rewrite |= self.lines.len() != len;
+    }
     
+    /// Locks the env-var file being written for the lifetime of the returned file, has to be taken
+    /// before the state is first accessed by changes depending on it. Nothing is locked with
+    /// --dry-run, which doesn't write.
+    pub(crate) fn lock() -> Result<Option<File>, WriterError> {
+        if CLI.dry_run {
+            return Ok(None);
+        }
+        let file = Self::lock_file()?;
+        FileExt::lock_exclusive(&file)?;
+        Ok(Some(file))
+    }
+
+    /// Same as `lock`, but `None` instead of waiting if another invocation holds the lock.
+    pub(crate) fn try_lock() -> Result<Option<File>, WriterError> {
+        let file = Self::lock_file()?;
+        match FileExt::try_lock_exclusive(&file) {
+            Ok(()) => Ok(Some(file)),
+            Err(error) if error.kind() == fs2::lock_contended_error().kind() => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Opens the lock file next to the env-var file, readers only lock the env-var file itself
+    /// while reading it.
+    fn lock_file() -> Result<File, WriterError> {
+        let location = Self::store_location();
+        if let Some(parent) = location.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut name = location.file_name().unwrap_or_default().to_os_string();
+        name.push(".lock");
+
+        Ok(OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(location.with_file_name(name))?)
+    }
+
     pub(crate) fn finalize() -> Result<(), WriterError> {
+        update_refresh_at()?;
+        Self::get_mut()?.bury();
         let this = Self::get()?;
+        this.check_locked()?;
         
         let file = if !this.rewrite {
             OpenOptions::new()
@@ -169,25 +362,85 @@ impl WriteManager {
             }
         }
         FileExt::unlock(&file)?;
+
+        // the terminal of an ended session is reused by later ones, which must not pick up its scope
+        if CLI.scope == Some(Scope::Tty) {
+            Scope::prune()?;
+        }
+
+        // the shells only watch the global env-var file, touching it makes them pick up the scope
+        if CLI.scope.is_some() {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(Self::global_location())?
+                .set_modified(SystemTime::now())?;
+        }
         
         Ok(())
     }
     
-    pub(crate) fn import(import: Vec<u8>) -> Result<(), WriterError> {
-        let lines = String::from_utf8_lossy(&import)
-            .lines()
-            .map(str::to_string)
+    /// Prints the lines the pending changes would remove from and add to the env-var file, instead
+    /// of writing them.
+    pub(crate) fn print_changes() -> Result<(), WriterError> {
+        Self::get_mut()?.bury();
+        let this = Self::get()?;
+        this.check_locked()?;
+        let lines = this.lines
+            .iter()
+            .chain(&this.append)
             .collect::<Vec<_>>();
 
-        EnvironmentVariable::from_lines(&lines)
+        let removed = this.original
+            .iter()
+            .filter(|line| !lines.contains(line))
+            .map(|line| format!("- {line}").red());
+        let added = lines
             .iter()
-            .try_for_each(EnvironmentVariable::delete)?;
+            .filter(|line| !this.original.contains(line))
+            .map(|line| format!("+ {line}").green());
 
-        let mut this = Self::get_mut()?;
+        let mut changed = false;
+        removed.chain(added)
+            .for_each(|line| {
+                println!("{line}");
+                changed = true;
+            });
+        if !changed {
+            println!("no changes");
+        }
+        Ok(())
+    }
 
-        lines.into_iter()
-            .for_each(|line| this.append(line));
+    pub(crate) fn import(import: Vec<EnvironmentVariable>, mode: ImportMode) -> Result<(), WriterError> {
+        if mode == ImportMode::Replace {
+            Self::clear()?;
+        }
+
+        latest(import).iter()
+            .try_for_each(|var| {
+                let line = var.encode();
+                let overwrite = match (mode, var.get_line()?) {
+                    (_, None) => true,
+                    (ImportMode::Keep, Some(_)) => false,
+                    (ImportMode::Interactive, Some(stored)) => {
+                        stored != line && confirm_overwrite(var, &stored, &line)?
+                    },
+                    _ => true,
+                };
 
+                if overwrite {
+                    var.delete()?;
+                    var.append()?;
+                }
+                Ok(())
+            })
+    }
+
+    pub(crate) fn replace(lines: Vec<String>) -> Result<(), WriterError> {
+        let mut this = Self::get_mut()?;
+        this.lines = lines;
+        this.rewrite = true;
         Ok(())
     }
 
@@ -198,34 +451,125 @@ impl WriteManager {
         Ok(())
     }
     
+    /// gexport's config directory holding the env-var files.
+    pub(crate) fn directory() -> PathBuf {
+        CLI.config_home.join("gexport")
+    }
+
+    /// Location of the env-var file, that of the scope given by --scope if any, without creating it.
+    pub(crate) fn store_location() -> PathBuf {
+        match CLI.scope {
+            Some(scope) => scope.location().expect("scope is checked on startup"),
+            None if CLI.system => Self::system_location(),
+            None => Self::global_location(),
+        }
+    }
+
+    /// Location of the env-var file shared by all shells.
+    pub(crate) fn global_location() -> PathBuf {
+        Self::directory().join("gexports")
+    }
+
+    /// Location of the env-var file set up by the administrator for all users, the first existing
+    /// one in $XDG_CONFIG_DIRS or /etc/gexport/gexports.
+    pub(crate) fn system_location() -> PathBuf {
+        env::var_os("XDG_CONFIG_DIRS")
+            .iter()
+            .flat_map(env::split_paths)
+            .filter(|directory| directory.is_absolute())
+            .map(|directory| directory.join("gexport").join("gexports"))
+            .find(|location| location.exists())
+            .unwrap_or_else(|| PathBuf::from("/etc/gexport/gexports"))
+    }
+
+    pub(crate) fn location(&self) -> &Path {
+        &self.location
+    }
+
+    /// Names of the stored variables in order of appearance, excluding gexport's own.
+    pub(crate) fn names(&self) -> Vec<String> {
+        self.lines
+            .iter()
+            .filter_map(|line| EnvironmentVariable::from_line(line).ok())
+            .filter(|var| !var.is_internal())
+            .map(|var| var.name)
+            .unique()
+            .collect()
+    }
+
     pub(crate) fn iter(&self) -> Iter<String> {
         self.lines.iter()
     }
+
+    /// Iterates the stored lines including those yet to be appended.
+    pub(crate) fn iter_pending(&self) -> impl Iterator<Item = &String> {
+        self.lines.iter().chain(&self.append)
+    }
+}
+
+/// Keeps only the last of the variables with the same name and conditions, in their order.
+fn latest(vars: Vec<EnvironmentVariable>) -> Vec<EnvironmentVariable> {
+    let mut latest = vars.into_iter()
+        .rev()
+        .unique_by(|var| (var.name.clone(), var.conditions.clone()))
+        .collect::<Vec<_>>();
+    latest.reverse();
+    latest
+}
+
+fn confirm_overwrite(var: &EnvironmentVariable, stored: &str, line: &str) -> Result<bool, WriterError> {
+    let prompt = format!(
+        "{}\n{}\n{}\noverwrite '{}'? [y/N] ",
+        var.name.bold(),
+        format!("- {stored}").red(),
+        format!("+ {line}").green(),
+        var.name,
+    );
+    Ok(confirm(&prompt)?)
 }
 
 #[derive(Debug)]
-pub(crate) struct WriterError {
-    cause: ErrorKind,
+pub(crate) enum WriterError {
+    Io(ErrorKind),
+    ReadOnly(String),
+    SystemLocked(String),
 }
 
 impl From<io::Error> for WriterError {
     fn from(error: io::Error) -> Self {
-        Self {
-            cause: error.kind()
-        }
+        Self::Io(error.kind())
     }
 }
 
 impl Display for WriterError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let error = match self.cause {
-            ErrorKind::PermissionDenied => str!("permission denied"),
-            ErrorKind::ReadOnlyFilesystem => str!("read-only filesystem"),
-            ErrorKind::ResourceBusy => str!("resource busy"),
-            error => format!("{error:?}")
+        let error = match self {
+            Self::Io(ErrorKind::PermissionDenied) => str!("permission denied"),
+            Self::Io(ErrorKind::ReadOnlyFilesystem) => str!("read-only filesystem"),
+            Self::Io(ErrorKind::ResourceBusy) => str!("resource busy"),
+            Self::Io(error) => format!("{error:?}"),
+            Self::ReadOnly(name) => return write!(f, "'{name}' is read-only, use --force to change it"),
+            Self::SystemLocked(name) => return write!(f, "'{name}' is locked by the system store"),
         };
         write!(f, "cannot write updated state, {error}")
     }
 }
 
-impl Error for WriterError {}
\ No newline at end of file
+impl Error for WriterError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(lines: &[&str]) -> Vec<EnvironmentVariable> {
+        lines.iter().map(|line| EnvironmentVariable::from_line(line).unwrap()).collect()
+    }
+
+    #[test]
+    fn keeps_last_of_duplicate_imports() {
+        let host = "[[ ${HOSTNAME:-$HOST} == build-* ]] && declare -gx A='3'";
+        let imported = vars(&["declare -gx A='1'", "declare -gx B='1'", host, "declare -gx A='2'"]);
+        let latest = latest(imported).iter().map(EnvironmentVariable::encode).collect::<Vec<_>>();
+        assert_eq!(latest, ["declare -gx B='1'", host, "declare -gx A='2'"]);
+    }
+}
@@ -1,17 +1,29 @@
 mod utils;
 mod args;
 mod env_vars;
+mod bundle;
+mod sync;
+mod edit;
+mod diff;
+mod which;
+mod doctor;
+mod completions;
+mod scope;
 
 use crate::args::*;
 use crate::utils::*;
-use crate::env_vars::EnvironmentVariable;
+use crate::env_vars::{is_tombstone, recompute, render, render_tombstones, Attribute, EncodeAction, EnvironmentVariable, Layer};
 use crate::env_vars::writer::{WriteManager, WriterError};
+use std::env;
+use std::ffi::OsString;
 use std::fmt::Display;
 use std::fmt;
 use std::process::ExitCode;
+use std::slice;
 use std::sync::{LazyLock, OnceLock};
 use std::io::Read;
 use clap::Parser;
+use itertools::Itertools;
 use clap_stdin::FileOrStdin;
 
 static CLI: LazyLock<Cli> = LazyLock::new(|| {
@@ -25,12 +37,58 @@ fn set_failure() {
 }
 
 fn main() -> ExitCode {
-    if let Some(shell) = CLI.init {
+    completions::complete();
+
+    if let Some(scope) = CLI.scope && scope.location().is_none() {
+        print_error(&format!("not in a {}, cannot use --scope", scope.description()));
+        return ExitCode::FAILURE;
+    }
+
+    if let Some(Command::Sync(command)) = &CLI.command {
+        sync::sync(command)
+            .inspect_err(print_error).ok();
+    } else if let Some(Command::Edit) = &CLI.command {
+        edit::edit()
+            .inspect_err(print_error).ok();
+    } else if let Some(Command::Diff { names }) = &CLI.command {
+        diff::diff(names)
+            .inspect_err(print_error).ok();
+    } else if let Some(Command::Which { name }) = &CLI.command {
+        which::which(name)
+            .inspect_err(print_error).ok();
+    } else if let Some(Command::Doctor) = &CLI.command {
+        doctor::doctor();
+    } else if let Some(shell) = CLI.init {
         print!("{shell}");
+    } else if let Some(shell) = CLI.completions {
+        completions::print_registration(shell)
+            .inspect_err(print_error).ok();
+    } else if CLI.scope_files {
+        Scope::locations()
+            .iter()
+            .for_each(|location| println!("{}", location.display()));
+    } else if CLI.system_file {
+        println!("{}", WriteManager::system_location().display());
+    } else if CLI.tombstones {
+        render_tombstones(Shell::Bash)
+            .map(|rendered| print!("{rendered}"))
+            .inspect_err(print_error).ok();
+    } else if let Some(shell) = CLI.render {
+        render(shell)
+            .map(|rendered| print!("{rendered}"))
+            .inspect_err(print_error).ok();
     } else if let Some(args) = &CLI.args {
         export(args);
     } else if let Some(data) = &CLI.import {
         import(data);
+    } else if let Some(names) = &CLI.export_bundle {
+        export_bundle(names);
+    } else if let Some(path) = &CLI.gen_key {
+        bundle::generate_key(path)
+            .inspect_err(print_error).ok();
+    } else if CLI.recompute {
+        recompute()
+            .inspect_err(print_error).ok();
     } else if CLI.clear {
         clear();
     } else {
@@ -41,16 +99,102 @@ fn main() -> ExitCode {
 }
 
 fn export(args: &GexportArgs) {
-    EnvironmentVariable::from_args(&args.vars)
-        .iter()
+    let _lock = match WriteManager::lock() {
+        Ok(lock) => lock,
+        Err(error) => return print_error(&error),
+    };
+    let action = if args.unset {
+        EncodeAction::Unset
+    } else if args.unexport {
+        EncodeAction::Unexport
+    } else if args.readonly {
+        EncodeAction::Readonly
+    } else {
+        EncodeAction::Default
+    };
+
+    let attribute = if args.integer {
+        Some(Attribute::Integer)
+    } else if args.lower {
+        Some(Attribute::Lower)
+    } else if args.upper {
+        Some(Attribute::Upper)
+    } else {
+        None
+    };
+
+    // patterns select from the stored variables for deletion, or from the environment for unset
+    let selected = if args.delete || args.unset {
+        let names = if args.delete {
+            match WriteManager::get() {
+                Ok(write_manager) => write_manager.names(),
+                Err(error) => return print_error(&error),
+            }
+        } else {
+            env::vars_os()
+                .filter_map(|(name, _)| name.into_string().ok())
+                .collect()
+        };
+        let expanded = args.vars
+            .iter()
+            .map(|selector| EnvironmentVariable::expand(slice::from_ref(selector), CLI.regex, &names))
+            .collect::<Result<Vec<_>, _>>();
+        match expanded {
+            Ok(expanded) => {
+                args.vars.iter()
+                    .zip(&expanded)
+                    .filter(|(_, names)| names.is_empty())
+                    .for_each(|(selector, _)| {
+                        let source = if args.delete { "stored" } else { "environment" };
+                        print_error(&format!("no {source} variable matches '{}'", selector.to_string_lossy()));
+                    });
+                expanded.into_iter().flatten().unique().collect()
+            },
+            Err(error) => return print_error(&error),
+        }
+    } else {
+        args.vars.clone()
+    };
+
+    let vars = EnvironmentVariable::from_args(&selected)
+        .into_iter()
+        .map(|var| {
+            var.with_action(action)
+                .with_conditions(&args.when)
+                .with_template(args.template)
+        })
+        .filter_map(|var| {
+            var.with_attribute(attribute)
+                .inspect_err(print_error).ok()
+        })
+        .filter_map(|var| {
+            if args.command || args.builtin {
+                var.with_producer(args.builtin, args.refresh)
+                    .inspect_err(print_error).ok()
+            } else {
+                Some(var)
+            }
+        })
+        .filter_map(|var| {
+            let stored = var.stored()
+                .inspect_err(print_error).ok()?;
+            Some(var.with_metadata(args.describe.as_deref(), &CLI.tag, stored.as_ref()))
+        })
+        .collect::<Vec<_>>();
+
+    vars.iter()
         .try_for_each(|var| {
-            var.delete()?;
+            if args.delete && args.when.is_empty() {
+                var.delete_all()?;
+            } else {
+                var.delete()?;
+            }
             if !args.delete {
                 var.append()?;
             }
             Ok(())
         })
-        .and_then(|_| WriteManager::finalize())
+        .and_then(|_| finalize())
         .unwrap_or_else(|error| {
             print_error(&error);
         });
@@ -65,57 +209,194 @@ fn import(data: &FileOrStdin) {
             import.read_to_end(&mut buf).ok()
         });
 
-    if success.is_some() {
-        WriteManager::import(buf)
-            .and_then(|_| WriteManager::finalize())
-            .inspect_err(|error| print_error(error)).ok();
-    } else {
+    if success.is_none() {
         print_error("could not read import");
+        return;
     }
-}
 
-fn print() {
-    if let Some(names) = &CLI.print
-        && !names.is_empty()
-    {
-        if let Err(error) = EnvironmentVariable::from_names(names)
-            .iter()
-            .map(EnvironmentVariable::get_line)
-            .try_for_each(|var| {
-                if let Some(var) = var? {
-                    println!("{var}");
+    let trusted = match CLI.verify.as_deref().map(bundle::read_verifying_keys).transpose() {
+        Ok(trusted) => trusted,
+        Err(error) => return print_error(&error),
+    };
+    // locked before checking the bundle, so concurrent imports of the same one can't both pass
+    let _lock = match WriteManager::lock() {
+        Ok(lock) => lock,
+        Err(error) => return print_error(&error),
+    };
+    // bundles of trusted keys no newer than the last imported one would roll back their values
+    let history = WriteManager::directory().join("bundles");
+    let mut verified = None;
+    if trusted.is_some() || bundle::is_bundle(&buf) {
+        if trusted.is_none() {
+            print_warning("the signature of the bundle is not checked, use --verify to only import bundles of trusted keys");
+        }
+        let opened = bundle::open(&buf, trusted.as_deref())
+            .and_then(|opened| {
+                if trusted.is_some() {
+                    bundle::check_replay(&opened, &history)?;
                 }
-                Ok::<_, WriterError>(())
-            })
-        {
-            print_error(&error);
+                Ok(opened)
+            });
+        match opened {
+            Ok(mut opened) => {
+                buf = std::mem::take(&mut opened.declarations);
+                verified = trusted.is_some().then_some(opened);
+            },
+            Err(error) => return print_error(&error),
+        }
+    }
+
+    if let Some(vars) = EnvironmentVariable::from_import(&buf) {
+        let imported = WriteManager::import(vars, CLI.mode)
+            .and_then(|_| finalize())
+            .inspect_err(print_error)
+            .is_ok();
+        if imported && !CLI.dry_run && let Some(opened) = verified {
+            bundle::record_import(&opened, &history)
+                .inspect_err(print_error).ok();
         }
     } else {
-        match WriteManager::get() {
-            Ok(write_mananager) => {
-                write_mananager
-                    .iter()
-                    .for_each(|line| {
-                        println!("{}", line);
+        print_error("import rejected, no environment variables were changed");
+    }
+}
+
+fn print() {
+    let names = CLI.print.as_deref().unwrap_or_default();
+    if CLI.layers {
+        return match Layer::all() {
+            Ok(layers) => {
+                layers.iter()
+                    .for_each(|layer| {
+                        println!("# {} ({})", layer.name, layer.location.display());
+                        print_lines(&layer_lines(layer, names));
                     });
             },
             Err(error) => print_error(&error),
-        }
+        };
+    }
+
+    match stored_lines(names) {
+        Ok(lines) => print_lines(&lines),
+        Err(error) => print_error(&error),
+    }
+}
+
+fn print_lines(lines: &[String]) {
+    lines.iter()
+        .filter_map(|line| {
+            match EnvironmentVariable::from_line(line) {
+                Ok(var) => var.has_tags(&CLI.tag).then_some((line, Some(var))),
+                Err(_) => CLI.tag.is_empty().then_some((line, None)),
+            }
+        })
+        .for_each(|(line, var)| {
+            match var.filter(|_| CLI.long) {
+                Some(var) if var.is_internal() => (),
+                Some(var) => {
+                    println!("{}", var.friendly());
+                    let metadata = var.describe();
+                    if !metadata.is_empty() {
+                        println!("{metadata}");
+                    }
+                },
+                None => println!("{}", line),
+            }
+        });
+}
+
+fn export_bundle(names: &[OsString]) {
+    let key = match bundle::read_signing_key(CLI.sign.as_ref().unwrap()) {
+        Ok(key) => key,
+        Err(error) => return print_error(&error),
+    };
+
+    match stored_lines(names) {
+        Ok(lines) => print!("{}", bundle::create(&lines, &key)),
+        Err(error) => print_error(&error),
+    }
+}
+
+/// Returns the stored lines of the selected environment variables, or all if none are selected.
+fn stored_lines(selectors: &[OsString]) -> Result<Vec<String>, WriterError> {
+    if selectors.is_empty() {
+        return Ok(WriteManager::get()?
+            .iter()
+            .filter(|line| !is_tombstone(line))
+            .cloned()
+            .collect());
+    }
+
+    let names = WriteManager::get()?.names();
+    let names = EnvironmentVariable::expand(selectors, CLI.regex, &names)
+        .inspect_err(print_error)
+        .unwrap_or_default();
+    EnvironmentVariable::from_names(&names)
+        .iter()
+        .map(EnvironmentVariable::get_lines)
+        .flatten_ok()
+        .collect()
+}
+
+/// Returns the lines of the selected environment variables in the layer, or all if none are selected.
+fn layer_lines(layer: &Layer, selectors: &[OsString]) -> Vec<String> {
+    if selectors.is_empty() {
+        return layer.lines
+            .iter()
+            .filter(|line| !is_tombstone(line))
+            .cloned()
+            .collect();
+    }
+
+    let names = EnvironmentVariable::expand(selectors, CLI.regex, &layer.names())
+        .inspect_err(print_error)
+        .unwrap_or_default();
+    layer.lines
+        .iter()
+        .filter(|line| {
+            EnvironmentVariable::from_line(line)
+                .is_ok_and(|var| names.iter().any(|name| name == var.name()))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Writes the pending changes, or only prints them with --dry-run.
+fn finalize() -> Result<(), WriterError> {
+    if CLI.dry_run {
+        WriteManager::print_changes()
+    } else {
+        WriteManager::finalize()
     }
 }
 
 fn clear() {
+    let _lock = match WriteManager::lock() {
+        Ok(lock) => lock,
+        Err(error) => return print_error(&error),
+    };
     WriteManager::clear()
-        .and_then(|_| WriteManager::finalize())
-        .inspect_err(|error| print_error(error)).ok();
+        .and_then(|_| finalize())
+        .inspect_err(print_error).ok();
 }
 
 impl Display for Shell {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, "{}", root_include_str!("init/init.sh"))?;
         match self {
-            Self::Bash => writeln!(f, "{}", root_include_str!("init/hook.bash")),
-            Self::Zsh => writeln!(f, "{}", root_include_str!("init/hook.zsh")),
+            Self::Bash => {
+                writeln!(f, "{}", root_include_str!("init/init.sh"))?;
+                writeln!(f, "{}", root_include_str!("init/hook.bash"))
+            },
+            Self::Zsh => {
+                writeln!(f, "{}", root_include_str!("init/init.sh"))?;
+                writeln!(f, "{}", root_include_str!("init/hook.zsh"))
+            },
+            // init.sh relies on bash and zsh features, the other shells have hooks of their own
+            Self::Sh => writeln!(f, "{}", root_include_str!("init/hook.sh")),
+            Self::Nushell => writeln!(f, "{}", root_include_str!("init/hook.nu")),
+            Self::Powershell => writeln!(f, "{}", root_include_str!("init/hook.ps1")),
+            Self::Tcsh => writeln!(f, "{}", root_include_str!("init/hook.tcsh")),
+            Self::Elvish => writeln!(f, "{}", root_include_str!("init/hook.elv")),
+            Self::Xonsh => writeln!(f, "{}", root_include_str!("init/hook.xsh")),
         }
     }
 }
@@ -0,0 +1,103 @@
+use crate::args::Scope;
+use crate::utils::{is_running, start_time, tty, tty_session};
+use crate::env_vars::writer::WriteManager;
+use std::env;
+use std::fs;
+use std::io;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use clap::ValueEnum;
+
+impl Scope {
+    /// Identifies the session or terminal the calling shell is in, `None` outside of one.
+    fn id(self) -> Option<String> {
+        let id = match self {
+            Self::Tmux => {
+                env::var_os("TMUX")?;
+                let output = Command::new("tmux")
+                    .args(["display-message", "-p", "#S"])
+                    .stdin(Stdio::null())
+                    .stderr(Stdio::null())
+                    .output()
+                    .ok()
+                    .filter(|output| output.status.success())?;
+                String::from_utf8_lossy(&output.stdout).trim().to_string()
+            },
+            Self::Screen => env::var("STY").ok()?,
+            // terminals are reused once closed, the session leader and its start time tell apart
+            // the sessions which had the same one
+            Self::Tty => {
+                let tty = tty()?;
+                let tty = tty.trim_start_matches("/dev/");
+                match tty_session() {
+                    Some(session) => match start_time(session) {
+                        Some(start) => format!("{tty}-{session}-{start}"),
+                        None => format!("{tty}-{session}"),
+                    },
+                    None => tty.to_string(),
+                }
+            },
+        };
+
+        // the id becomes part of a file name
+        (!id.is_empty()).then(|| {
+            id.chars()
+                .map(|char| if char.is_ascii_alphanumeric() || "._-".contains(char) { char } else { '_' })
+                .collect()
+        })
+    }
+
+    /// Location of the env-var file of the scope, `None` outside of it.
+    pub(crate) fn location(self) -> Option<PathBuf> {
+        let name = self.to_possible_value()?.get_name().to_string();
+        Some(WriteManager::directory().join("scopes").join(format!("{name}-{}", self.id()?)))
+    }
+
+    /// Env-var files of the scopes the calling shell is in, in order of increasing precedence.
+    pub(crate) fn locations() -> Vec<PathBuf> {
+        Self::value_variants()
+            .iter()
+            .filter_map(|scope| scope.location())
+            .collect()
+    }
+
+    /// Removes the env-var files of terminal sessions which have ended.
+    pub(crate) fn prune() -> io::Result<()> {
+        let directory = WriteManager::directory().join("scopes");
+        let entries = match fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(error),
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some(id) = name.strip_prefix("tty-") else {
+                continue;
+            };
+            // <tty>-<session>[-<start>], files without a session are left from older versions
+            let mut fields = id.split('-').skip(1);
+            let session = fields.next().and_then(|session| session.parse().ok());
+            let start = fields.next().map(|start| start.parse().ok());
+            let running = match (session, start) {
+                (Some(session), None) => is_running(session),
+                (Some(session), Some(start)) => start.is_some() && start_time(session) == start,
+                (None, _) => false,
+            };
+            if !running {
+                fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn description(self) -> &'static str {
+        match self {
+            Self::Tmux => "tmux session",
+            Self::Screen => "screen session",
+            Self::Tty => "terminal",
+        }
+    }
+}
@@ -0,0 +1,429 @@
+use crate::str;
+use crate::args::SyncCommand;
+use crate::utils::{hostname, print_error, print_warning};
+use crate::env_vars::{is_tombstone, Condition, EnvironmentVariable, ParserError, Producer};
+use crate::env_vars::writer::{WriteManager, WriterError};
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fmt::Display;
+use std::io;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::process::Command;
+use itertools::Itertools;
+
+const BRANCH: &str = "main";
+const REMOTE: &str = "origin";
+
+pub(crate) fn sync(command: &SyncCommand) -> Result<(), SyncError> {
+    let _lock = WriteManager::lock()?;
+    let repository = Repository::open()?;
+    let store = |lines| {
+        WriteManager::replace(lines)?;
+        Ok(WriteManager::finalize()?)
+    };
+    match command {
+        SyncCommand::Init { remote } => repository.init(remote, &store),
+        SyncCommand::Push => repository.push(&store),
+        SyncCommand::Pull => repository.pull(&store),
+    }
+}
+
+/// Stores the merged lines in the env-var file.
+type Store<'a> = &'a dyn Fn(Vec<String>) -> Result<(), SyncError>;
+
+/// Git repository in gexport's config directory tracking the env-var file.
+struct Repository {
+    directory: PathBuf,
+    file: PathBuf,
+}
+
+impl Repository {
+    fn open() -> Result<Self, SyncError> {
+        let write_manager = WriteManager::get()?;
+        let location = write_manager.location();
+        Ok(Self {
+            directory: location.parent().unwrap().to_path_buf(),
+            file: PathBuf::from(location.file_name().unwrap()),
+        })
+    }
+
+    fn init(&self, remote: &OsStr, store: Store) -> Result<(), SyncError> {
+        if !self.directory.join(".git").exists() {
+            self.git(["init", "--quiet"])?;
+            self.git(["symbolic-ref", "HEAD", &format!("refs/heads/{BRANCH}")])?;
+        }
+
+        if self.git(["remote", "get-url", REMOTE]).is_ok() {
+            self.git([OsStr::new("remote"), OsStr::new("set-url"), OsStr::new(REMOTE), remote])?;
+        } else {
+            self.git([OsStr::new("remote"), OsStr::new("add"), OsStr::new(REMOTE), remote])?;
+        }
+
+        self.push(store)
+    }
+
+    fn push(&self, store: Store) -> Result<(), SyncError> {
+        self.pull(store)?;
+        self.git(["push", "--quiet", REMOTE, &format!("HEAD:refs/heads/{BRANCH}")])?;
+        Ok(())
+    }
+
+    fn pull(&self, store: Store) -> Result<(), SyncError> {
+        self.ensure_initialized()?;
+        self.commit_local()?;
+
+        if self.git(["ls-remote", "--heads", REMOTE, BRANCH])?.is_empty() {
+            return Ok(());
+        }
+        self.git(["fetch", "--quiet", REMOTE, BRANCH])?;
+
+        if self.is_ancestor("FETCH_HEAD", "HEAD") {
+            return Ok(());
+        }
+
+        let base = self.git(["merge-base", "HEAD", "FETCH_HEAD"])
+            .ok()
+            .map(|base| self.show(&base))
+            .transpose()?
+            .unwrap_or_default();
+        let ours = self.show("HEAD")?;
+        let theirs = self.show("FETCH_HEAD")?;
+        let merged = merge(&base, &ours, &theirs)?;
+
+        let fast_forward = self.is_ancestor("HEAD", "FETCH_HEAD");
+        if fast_forward {
+            self.git(["reset", "--soft", "--quiet", "FETCH_HEAD"])?;
+        } else {
+            self.git([
+                "merge", "--quiet", "--no-ff", "--no-commit", "--allow-unrelated-histories",
+                "--strategy=ours", "FETCH_HEAD",
+            ])?;
+        }
+
+        store(merged)?;
+
+        if fast_forward {
+            self.git(["add", "--", self.file.to_str().unwrap()])?;
+            Ok(())
+        } else {
+            self.commit(&format!("merge {REMOTE}/{BRANCH} on {}", hostname()))
+        }
+    }
+
+    fn ensure_initialized(&self) -> Result<(), SyncError> {
+        // the config directory might be part of another repository, e.g. tracked dotfiles
+        if !self.directory.join(".git").exists() || self.git(["remote", "get-url", REMOTE]).is_err() {
+            Err(SyncError::NotInitialized)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn commit_local(&self) -> Result<(), SyncError> {
+        let head = self.git(["rev-parse", "--verify", "--quiet", "HEAD"]).is_ok();
+        if head && self.git(["status", "--porcelain", "--", self.file.to_str().unwrap()])?.is_empty() {
+            return Ok(());
+        }
+        self.commit(&format!("update on {}", hostname()))
+    }
+
+    fn commit(&self, message: &str) -> Result<(), SyncError> {
+        self.git(["add", "--", self.file.to_str().unwrap()])?;
+        self.git(["commit", "--quiet", "--allow-empty", "-m", message])?;
+        Ok(())
+    }
+
+    fn show(&self, revision: &str) -> Result<Vec<String>, SyncError> {
+        let file = format!("{revision}:{}", self.file.display());
+        Ok(self.git(["show", &file])
+            .map(|content| content.lines().map(str::to_string).collect())
+            .unwrap_or_default())
+    }
+
+    fn is_ancestor(&self, ancestor: &str, descendant: &str) -> bool {
+        self.git(["merge-base", "--is-ancestor", ancestor, descendant]).is_ok()
+    }
+
+    fn git<I, S>(&self, args: I) -> Result<String, SyncError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut command = Command::new("git");
+        command.arg("-C").arg(&self.directory);
+
+        // commits need an identity, fall back to one derived from the host if none is configured
+        let identity = Command::new("git")
+            .arg("-C").arg(&self.directory)
+            .args(["config", "user.email"])
+            .output()
+            .is_ok_and(|output| output.status.success());
+        if !identity {
+            let user = std::env::var("USER").unwrap_or(str!("gexport"));
+            command
+                .arg("-c").arg(format!("user.name={user}"))
+                .arg("-c").arg(format!("user.email={user}@{}", hostname()));
+        }
+
+        let output = command.args(args).output()?;
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        } else {
+            Err(SyncError::Git(String::from_utf8_lossy(&output.stderr).trim().to_string()))
+        }
+    }
+}
+
+/// Three-way merge of env-var files, resolved per variable and its conditional variants.
+///
+/// The order of the local file is kept, variables only present remotely are appended. If a variable
+/// was changed on both sides the local line is kept. Lines are compared without the values and
+/// times of computed variables, which are computed on each machine, and gexport's own variables
+/// are left to the machine. Remote changes to the producer of a variable are refused, so a pull
+/// never installs commands to run.
+fn merge(base: &[String], ours: &[String], theirs: &[String]) -> Result<Vec<String>, SyncError> {
+    let base = by_variant(base)?;
+    let ours = by_variant(ours)?;
+    let theirs = by_variant(theirs)?;
+
+    let find = |lines: &Vec<(Variant, Line)>, variant: &Variant| {
+        lines.iter()
+            .find(|(other, _)| other == variant)
+            .map(|(_, line)| line.clone())
+    };
+    let portable = |line: &Option<Line>| line.as_ref().map(|line| line.portable.clone());
+
+    Ok(ours.iter()
+        .chain(theirs.iter())
+        .map(|(variant, _)| variant)
+        .unique()
+        .filter_map(|variant| {
+            let base = portable(&find(&base, variant));
+            let ours = find(&ours, variant);
+            let theirs = find(&theirs, variant);
+
+            let line = if portable(&ours) == portable(&theirs) || portable(&theirs) == base {
+                ours
+            } else if portable(&ours) == base {
+                let producer = |line: &Option<Line>| line.as_ref().and_then(|line| line.producer.clone());
+                if producer(&theirs).is_some() && producer(&theirs) != producer(&ours) {
+                    let message = format!("'{}' is computed differently remotely, keeping the local value", variant.0);
+                    print_warning(&message);
+                    ours
+                } else {
+                    theirs
+                }
+            } else {
+                print_error(&format!("conflicting changes to '{}', keeping the local value", variant.0));
+                ours
+            };
+            line.map(|line| line.stored)
+        })
+        .collect())
+}
+
+/// Name and conditions identifying a variable in the env-var file.
+type Variant = (String, Vec<Condition>);
+
+/// Line of the env-var file, along with the line as it is compared across machines.
+#[derive(Clone)]
+struct Line {
+    stored: String,
+    portable: String,
+    producer: Option<Producer>,
+}
+
+fn by_variant(lines: &[String]) -> Result<Vec<(Variant, Line)>, SyncError> {
+    lines.iter()
+        // tombstones are left again for the variables the merge removes
+        .filter(|line| !line.trim().is_empty() && !is_tombstone(line))
+        .map(|line| EnvironmentVariable::from_line(line).map(|var| (line, var)))
+        .filter_ok(|(_, var)| !var.is_internal())
+        .map(|parsed| {
+            let (line, var) = parsed?;
+            let line = Line {
+                stored: line.clone(),
+                portable: var.portable().encode(),
+                producer: var.producer().cloned(),
+            };
+            Ok(((var.name().to_string(), var.conditions().to_vec()), line))
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+pub(crate) enum SyncError {
+    Io(ErrorKind),
+    Git(String),
+    Writer(WriterError),
+    Parser(ParserError),
+    NotInitialized,
+}
+
+impl From<io::Error> for SyncError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error.kind())
+    }
+}
+
+impl From<WriterError> for SyncError {
+    fn from(error: WriterError) -> Self {
+        Self::Writer(error)
+    }
+}
+
+impl From<ParserError> for SyncError {
+    fn from(error: ParserError) -> Self {
+        Self::Parser(error)
+    }
+}
+
+impl Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(ErrorKind::NotFound) => write!(f, "cannot sync, git is not installed"),
+            Self::Io(error) => write!(f, "cannot sync, {error:?}"),
+            Self::Git(error) => write!(f, "cannot sync, git failed: {error}"),
+            Self::Writer(error) => write!(f, "{error}"),
+            Self::Parser(error) => write!(f, "cannot sync, {error}"),
+            Self::NotInitialized => write!(f, "sync is not set up, see 'gexport sync init'"),
+        }
+    }
+}
+
+impl Error for SyncError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::process;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn merges_changes_of_either_side() {
+        let base = lines(&["declare -gx A='1'", "declare -gx B='1'", "declare -gx C='1'"]);
+        let ours = lines(&["declare -gx A='2'", "declare -gx B='1'", "declare -gx C='1'"]);
+        let theirs = lines(&["declare -gx A='1'", "declare -gx B='2'", "declare -gx C='1'", "declare -gx D='1'"]);
+        let merged = merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(merged, lines(&["declare -gx A='2'", "declare -gx B='2'", "declare -gx C='1'", "declare -gx D='1'"]));
+    }
+
+    #[test]
+    fn merges_deletions_of_either_side() {
+        let base = lines(&["declare -gx A='1'", "declare -gx B='1'", "declare -gx C='1'"]);
+        let ours = lines(&["declare -gx B='1'", "declare -gx C='1'"]);
+        let theirs = lines(&["declare -gx A='1'", "declare -gx C='1'"]);
+        assert_eq!(merge(&base, &ours, &theirs).unwrap(), lines(&["declare -gx C='1'"]));
+    }
+
+    #[test]
+    fn keeps_local_line_on_conflict() {
+        let base = lines(&["declare -gx A='1'"]);
+        let ours = lines(&["declare -gx A='2'"]);
+        let theirs = lines(&["declare -gx A='3'"]);
+        assert_eq!(merge(&base, &ours, &theirs).unwrap(), ours);
+        // a deletion conflicting with a change keeps the local side as well
+        assert!(merge(&base, &[], &theirs).unwrap().is_empty());
+    }
+
+    #[test]
+    fn merges_conditional_variants_separately() {
+        let host = "[[ ${HOSTNAME:-$HOST} == build-* ]] && declare -gx A='build'";
+        let os = "[[ ${OSTYPE%%[0-9.-]*} == darwin ]] && declare -gx A='mac'";
+        let base = lines(&["declare -gx A='1'"]);
+        let ours = lines(&["declare -gx A='1'", host]);
+        let theirs = lines(&["declare -gx A='2'", os]);
+        assert_eq!(merge(&base, &ours, &theirs).unwrap(), lines(&["declare -gx A='2'", host, os]));
+    }
+
+    #[test]
+    fn skips_tombstones_and_internal_variables() {
+        let base = lines(&["declare -gx A='1'", "declare -g +x GEXPORT_REFRESH_AT='100'"]);
+        let ours = lines(&["declare -gx A='1'", "# deleted B", "declare -g +x GEXPORT_REFRESH_AT='200'"]);
+        let theirs = lines(&["declare -gx A='1'", "# deleted C", "declare -g +x GEXPORT_REFRESH_AT='300'"]);
+        assert_eq!(merge(&base, &ours, &theirs).unwrap(), lines(&["declare -gx A='1'"]));
+    }
+
+    #[test]
+    fn ignores_values_computed_on_each_machine() {
+        let base = lines(&["declare -gx A='x' # command='hostname' refresh='60' updated='100'"]);
+        let ours = base.clone();
+        let theirs = lines(&["declare -gx A='y' # command='hostname' refresh='60' updated='200'"]);
+        assert_eq!(merge(&base, &ours, &theirs).unwrap(), ours);
+
+        // changes to the producer itself are refused, as are remote producers of new variables
+        let theirs = lines(&["declare -gx A='z' # command='touch /tmp/x' refresh='60' updated='200'"]);
+        assert_eq!(merge(&base, &ours, &theirs).unwrap(), ours);
+        let theirs = lines(&["declare -gx A='x'", "declare -gx B='z' # command='touch /tmp/x' updated='200'"]);
+        assert_eq!(merge(&[], &[], &theirs).unwrap(), lines(&["declare -gx A='x'"]));
+        let theirs = lines(&["declare -gx A='x'"]);
+        assert_eq!(merge(&base, &ours, &theirs).unwrap(), theirs);
+    }
+
+    /// Scratch directory removed on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = env::temp_dir().join(format!("gexport-test-{name}-{}", process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn machine(directory: &Path, content: &str) -> Repository {
+        fs::create_dir_all(directory).unwrap();
+        fs::write(directory.join("gexports"), content).unwrap();
+        Repository {
+            directory: directory.to_path_buf(),
+            file: PathBuf::from("gexports"),
+        }
+    }
+
+    fn read(repository: &Repository) -> String {
+        fs::read_to_string(repository.directory.join(&repository.file)).unwrap()
+    }
+
+    #[test]
+    fn pushes_and_pulls_through_bare_repository() {
+        let temp = TempDir::new("sync");
+        let remote = temp.0.join("remote.git");
+        Command::new("git").args(["init", "--quiet", "--bare"]).arg(&remote).status().unwrap();
+
+        let a = machine(&temp.0.join("a"), "declare -gx A='1'\n");
+        let b = machine(&temp.0.join("b"), "declare -gx B='1'\n");
+        let store = |repository: &Repository| {
+            let file = repository.directory.join(&repository.file);
+            move |lines: Vec<String>| Ok(fs::write(&file, lines.iter().map(|line| format!("{line}\n")).collect::<String>())?)
+        };
+        let (store_a, store_b) = (store(&a), store(&b));
+
+        a.init(remote.as_os_str(), &store_a).unwrap();
+        b.init(remote.as_os_str(), &store_b).unwrap();
+        assert_eq!(read(&b), "declare -gx B='1'\ndeclare -gx A='1'\n");
+
+        a.pull(&store_a).unwrap();
+        assert_eq!(read(&a), "declare -gx A='1'\ndeclare -gx B='1'\n");
+
+        fs::write(a.directory.join(&a.file), "declare -gx A='2'\ndeclare -gx B='1'\n").unwrap();
+        a.push(&store_a).unwrap();
+        fs::write(b.directory.join(&b.file), "declare -gx B='1'\ndeclare -gx A='1'\ndeclare -gx C='1'\n").unwrap();
+        b.pull(&store_b).unwrap();
+        assert_eq!(read(&b), "declare -gx B='1'\ndeclare -gx A='2'\ndeclare -gx C='1'\n");
+    }
+}
@@ -1,7 +1,13 @@
 use crate::set_failure;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::env;
+use std::ffi::CStr;
 use std::fmt::Display;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
 use colored::Colorize;
 
 #[macro_export]
@@ -34,3 +40,179 @@ pub(crate) fn print_error<E: Display + ?Sized>(error: &E) {
     eprintln!("{} {}", "error:".red().bold(), error);
     set_failure();
 }
+
+pub(crate) fn print_warning<W: Display + ?Sized>(warning: &W) {
+    eprintln!("{} {}", "warning:".yellow().bold(), warning);
+}
+
+pub(crate) fn confirm(prompt: &str) -> io::Result<bool> {
+    // stdin may already be in use as the source of an import, ask the terminal directly
+    let mut tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")?;
+    write!(tty, "{prompt}")?;
+    tty.flush()?;
+
+    let mut answer = String::new();
+    BufReader::new(&tty).read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+pub(crate) fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    // SAFETY: the buffer is valid for writes of its length, the result is NUL terminated on success
+    let result = unsafe {
+        libc::gethostname(buf.as_mut_ptr().cast(), buf.len())
+    };
+
+    if result == 0 {
+        let end = buf.iter().position(|&byte| byte == 0).unwrap_or(buf.len());
+        String::from_utf8_lossy(&buf[..end]).to_string()
+    } else {
+        String::new()
+    }
+}
+
+/// Path of the terminal the standard input or error is connected to, e.g. `/dev/pts/3`.
+pub(crate) fn tty() -> Option<String> {
+    [libc::STDIN_FILENO, libc::STDERR_FILENO]
+        .into_iter()
+        .find_map(|fd| {
+            // SAFETY: ttyname returns NULL or a NUL terminated string in a static buffer, which is
+            // copied before ttyname is called again
+            let name = unsafe { libc::ttyname(fd) };
+            (!name.is_null()).then(|| unsafe { CStr::from_ptr(name) }.to_string_lossy().to_string())
+        })
+}
+
+/// Leader of the session owning the terminal the standard input or error is connected to, the
+/// terminal's shell in most cases.
+pub(crate) fn tty_session() -> Option<i32> {
+    [libc::STDIN_FILENO, libc::STDERR_FILENO]
+        .into_iter()
+        // SAFETY: tcgetsid has no preconditions, it fails on fds which aren't a controlling terminal
+        .map(|fd| unsafe { libc::tcgetsid(fd) })
+        .find(|session| *session > 0)
+}
+
+/// Time the process started at in clock ticks since boot, `None` if it doesn't exist or the time
+/// isn't available, as outside of Linux.
+pub(crate) fn start_time(pid: i32) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // the command name in parentheses may contain spaces, the start time is the 22nd field
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(19)?.parse().ok()
+}
+
+/// Whether a process with the pid exists.
+pub(crate) fn is_running(pid: i32) -> bool {
+    // SAFETY: signal 0 only checks whether the signal could be sent
+    let sent = unsafe { libc::kill(pid, 0) } == 0;
+    sent || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// The current user as `user@host`, or just the host if the user is unknown.
+pub(crate) fn owner() -> String {
+    match env::var("USER") {
+        Ok(user) if !user.is_empty() => format!("{user}@{}", hostname()),
+        _ => hostname(),
+    }
+}
+
+/// Name of the shell gexport was called from, falls back to the login shell.
+pub(crate) fn current_shell() -> String {
+    // SAFETY: getppid has no preconditions and cannot fail
+    let parent = unsafe { libc::getppid() };
+    fs::read_to_string(format!("/proc/{parent}/comm"))
+        .ok()
+        .or_else(|| {
+            env::var_os("SHELL")
+                .and_then(|shell| Some(Path::new(&shell).file_name()?.to_string_lossy().to_string()))
+        })
+        .map(|shell| shell.trim().trim_start_matches('-').to_string())
+        .unwrap_or_default()
+}
+
+/// Modification time of the file in seconds since the unix epoch, as `stat -c %Y` prints it.
+pub(crate) fn mod_time(path: &Path) -> Option<u64> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|modified| modified.as_secs())
+}
+
+/// Matches the wildcards `*` and `?` like the shell does in `[[ ... == pattern ]]`.
+/// Only the last `*` is ever backtracked to, as anything an earlier star could match more is
+/// covered by the later one, so matching takes at most pattern times subject length steps.
+pub(crate) fn glob_match(pattern: &[u8], subject: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // position after the last star and the subject position it was matched from
+    let mut star = None;
+
+    while s < subject.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                p += 1;
+                star = Some((p, s));
+            },
+            Some(&expected) if expected == b'?' || expected == subject[s] => {
+                p += 1;
+                s += 1;
+            },
+            _ => match star {
+                Some((after, from)) => {
+                    p = after;
+                    s = from + 1;
+                    star = Some((after, s));
+                },
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&byte| byte == b'*')
+}
+
+/// Seconds since the unix epoch.
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_matches_wildcards() {
+        assert!(glob_match(b"AWS_*", b"AWS_PROFILE"));
+        assert!(glob_match(b"AWS_*", b"AWS_"));
+        assert!(glob_match(b"*", b""));
+        assert!(glob_match(b"?", b"a"));
+        assert!(glob_match(b"a*b*c", b"aXbYbc"));
+        assert!(glob_match(b"build-??", b"build-01"));
+        assert!(glob_match(b"exact", b"exact"));
+    }
+
+    #[test]
+    fn glob_rejects_mismatches() {
+        assert!(!glob_match(b"AWS_*", b"XAWS_PROFILE"));
+        assert!(!glob_match(b"?", b""));
+        assert!(!glob_match(b"?", b"ab"));
+        assert!(!glob_match(b"", b"a"));
+        assert!(!glob_match(b"a*b", b"acbd"));
+        assert!(!glob_match(b"exact", b"exactly"));
+    }
+
+    #[test]
+    fn glob_backtracks_only_to_the_last_star() {
+        let subject = [b'a'; 10_000];
+        assert!(!glob_match(b"*a*a*a*a*a*a*a*a*b", &subject));
+        assert!(glob_match(b"*a*a*a*a*a*a*a*a*", &subject));
+        assert!(glob_match(b"*?a*a", b"xaba"));
+    }
+}
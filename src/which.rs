@@ -0,0 +1,61 @@
+use crate::utils::print_error;
+use crate::env_vars::{EncodeAction, Layer};
+use crate::env_vars::writer::WriterError;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+/// Prints the variable in effect for the calling shell and the layer it comes from.
+pub(crate) fn which(name: &OsStr) -> Result<(), WriterError> {
+    let layers = Layer::all()?;
+
+    // the layers are sourced in order of increasing precedence and each in order, so the last
+    // applying line of the variable wins
+    let mut values = HashMap::new();
+    let mut defined = Vec::new();
+    for layer in &layers {
+        let mut found = None;
+        for var in layer.vars() {
+            let value = (var.action() != EncodeAction::Unset).then(|| {
+                var.resolve(|name| {
+                    values.get(name)
+                        .cloned()
+                        .unwrap_or_else(|| env::var_os(name).map(OsString::into_vec))
+                })
+            });
+            values.insert(var.name().to_string(), value);
+            if var.name().as_bytes() == name.as_bytes() {
+                found = Some(var);
+            }
+        }
+        if let Some(var) = found {
+            defined.push((layer, var));
+        }
+    }
+
+    let Some(((layer, var), overridden)) = defined.split_last() else {
+        print_error(&format!("'{}' is not stored in any layer that applies", name.to_string_lossy()));
+        return Ok(());
+    };
+
+    println!("{}", var.friendly());
+    if var.is_template() && let Some(Some(value)) = values.get(var.name()) {
+        println!("    value: {}", String::from_utf8_lossy(value));
+    }
+    println!("    layer: {} ({})", layer.name, layer.location.display());
+    if !overridden.is_empty() {
+        let overridden = overridden
+            .iter()
+            .map(|(layer, _)| layer.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        println!("    overrides: {overridden}");
+    }
+    let metadata = var.describe();
+    if !metadata.is_empty() {
+        println!("{metadata}");
+    }
+
+    Ok(())
+}